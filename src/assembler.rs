@@ -0,0 +1,943 @@
+use crate::rom::PROGRAM_START;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Write;
+
+// Highest address a ROM can fill, matching the 64K of XO-CHIP memory.
+const MEMORY_END: usize = 0x10000;
+
+// Macros can invoke other macros up to this deep.
+const MAX_MACRO_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "line {}: {}", self.line, self.message);
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+pub struct Assembled {
+    pub rom: Vec<u8>,
+    pub labels: BTreeMap<String, u16>
+}
+
+impl Assembled {
+    // One "address name" line per label, in address order.
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<(&u16, &String)> = self.labels.iter().map(|(name, address)| (address, name)).collect();
+        symbols.sort();
+        let mut out = String::new();
+        for (address, name) in symbols {
+            writeln!(out, "0x{:04X} {}", address, name).unwrap();
+        }
+        return out;
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    // How many macro expansions deep the token came from; 0 for source text.
+    depth: usize
+}
+
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Token>
+}
+
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // The low 12 bits of the opcode at the fixup address.
+    Address,
+    // The whole word at the fixup address, following F000.
+    Long
+}
+
+struct Fixup {
+    address: usize,
+    name: String,
+    kind: FixupKind,
+    line: usize
+}
+
+// Open if/else and loop blocks, holding the addresses of jumps still waiting for a target.
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: usize, breaks: Vec<usize> }
+}
+
+enum Operand {
+    Register(usize),
+    Byte(u8)
+}
+
+enum Condition {
+    Equal(usize, Operand),
+    NotEqual(usize, Operand),
+    Key(usize),
+    NotKey(usize),
+    // Comparison operator and operands, computed through VF.
+    Compare(String, usize, Operand)
+}
+
+// Assembles Octo source into a ROM that loads at 0x200. Classic mnemonics such as "LD V0, 0x05" and
+// "DRW V0, V1, 5" may be mixed in. They must be written in upper case, so lowercase Octo names such as
+// "sub" stay free, and take their operands from the rest of the line.
+pub fn assemble(source: &str) -> Result<Assembled, AsmError> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        rom: Vec::new(),
+        here: PROGRAM_START,
+        labels: BTreeMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        line: 1
+    };
+    assembler.run()?;
+    return Ok(Assembled { rom: assembler.rom, labels: assembler.labels });
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        for text in code.split(|c: char| c.is_whitespace() || c == ',').filter(|text| !text.is_empty()) {
+            tokens.push_back(Token { text: String::from(text), line: index + 1, depth: 0 });
+        }
+    }
+    return tokens;
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text)
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse::<i64>().ok()?
+    } else {
+        return None;
+    };
+    return Some(if negative { -value } else { value });
+}
+
+fn is_identifier(text: &str) -> bool {
+    return text.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    here: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    line: usize
+}
+
+impl Assembler {
+    fn run(&mut self) -> Result<(), AsmError> {
+        // Like Octo, start with a jump to main unless main is the first thing in the program.
+        // Either ": main" or the classic "main:".
+        let defines_main = |index: usize| self.tokens[index].text == "main:"
+            || (self.tokens[index].text == ":" && self.tokens.get(index + 1).is_some_and(|name| name.text == "main"));
+        let starts_with_main = !self.tokens.is_empty() && defines_main(0);
+        let has_main = (0..self.tokens.len()).any(defines_main);
+        if has_main && !starts_with_main {
+            let main = Token { text: String::from("main"), line: 1, depth: 0 };
+            self.emit_address(0x1000u16, &main)?;
+        }
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(token)?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let name = match block {
+                Block::If { .. } | Block::Else { .. } => "begin",
+                Block::Loop { .. } => "loop"
+            };
+            return Err(self.error(format!("'{}' is never closed", name)));
+        }
+        for fixup in self.fixups.iter() {
+            let address = match self.labels.get(&fixup.name) {
+                Some(address) => *address,
+                None => return Err(AsmError { line: fixup.line, message: format!("Undefined name '{}'", fixup.name) })
+            };
+            let index = fixup.address - PROGRAM_START;
+            match fixup.kind {
+                FixupKind::Address => {
+                    if address > 0x0FFFu16 {
+                        return Err(AsmError {
+                            line: fixup.line,
+                            message: format!("Label '{}' at 0x{:04X} is out of reach; use 'i := long'", fixup.name, address)
+                        });
+                    }
+                    self.rom[index] |= (address >> 8) as u8;
+                    self.rom[index + 1] = address as u8;
+                }
+                FixupKind::Long => {
+                    self.rom[index] = (address >> 8) as u8;
+                    self.rom[index + 1] = address as u8;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        let text = token.text.as_str();
+        if let Some(register) = self.register(text) {
+            return self.register_statement(register);
+        }
+        if let Some(value) = parse_number(text) {
+            return self.emit_byte_value(value);
+        }
+        if self.macros.contains_key(text) {
+            return self.expand_macro(&token);
+        }
+        if text.len() > 1 && text.ends_with(':') && is_identifier(&text[..text.len() - 1]) {
+            return self.define_label(&text[..text.len() - 1]);
+        }
+        match text {
+            ":" => {
+                let name = self.next_token()?;
+                return self.define_label(&name.text);
+            }
+            ":const" => {
+                let name = self.next_identifier()?;
+                let value_token = self.next_token()?;
+                let value = match self.constant_value(&value_token.text) {
+                    Some(value) => value,
+                    None => return Err(self.error(format!("Expected a value, got '{}'", value_token.text)))
+                };
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next_identifier()?;
+                let register = self.next_register()?;
+                self.aliases.insert(name, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.next_number(PROGRAM_START as i64, MEMORY_END as i64 - 1)?;
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let value = self.next_number(-128, 255)?;
+                self.emit_byte(value as u8)?;
+            }
+            "clear" => self.emit(0x00E0u16)?,
+            "return" | ";" => self.emit(0x00EEu16)?,
+            "scroll-down" => {
+                let n = self.next_number(0, 15)?;
+                self.emit(0x00C0u16 | n as u16)?;
+            }
+            "scroll-up" => {
+                let n = self.next_number(0, 15)?;
+                self.emit(0x00D0u16 | n as u16)?;
+            }
+            "scroll-right" => self.emit(0x00FBu16)?,
+            "scroll-left" => self.emit(0x00FCu16)?,
+            "exit" => self.emit(0x00FDu16)?,
+            "lores" => self.emit(0x00FEu16)?,
+            "hires" => self.emit(0x00FFu16)?,
+            "native" => {
+                let target = self.next_token()?;
+                self.emit_address(0x0000u16, &target)?;
+            }
+            "jump" => {
+                let target = self.next_token()?;
+                self.emit_address(0x1000u16, &target)?;
+            }
+            "jump0" => {
+                let target = self.next_token()?;
+                self.emit_address(0xB000u16, &target)?;
+            }
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.next_number(0, 15)?;
+                self.emit(0xD000u16 | (x as u16) << 8 | (y as u16) << 4 | n as u16)?;
+            }
+            "bcd" => {
+                let x = self.next_register()?;
+                self.emit(0xF033u16 | (x as u16) << 8)?;
+            }
+            "save" | "load" => {
+                let x = self.next_register()?;
+                let range = self.tokens.front().is_some_and(|next| next.text == "-");
+                if range {
+                    self.tokens.pop_front();
+                    let y = self.next_register()?;
+                    let low = if text == "save" { 0x2u16 } else { 0x3u16 };
+                    self.emit(0x5000u16 | (x as u16) << 8 | (y as u16) << 4 | low)?;
+                } else {
+                    let low = if text == "save" { 0x55u16 } else { 0x65u16 };
+                    self.emit(0xF000u16 | (x as u16) << 8 | low)?;
+                }
+            }
+            "saveflags" => {
+                let x = self.next_register()?;
+                self.emit(0xF075u16 | (x as u16) << 8)?;
+            }
+            "loadflags" => {
+                let x = self.next_register()?;
+                self.emit(0xF085u16 | (x as u16) << 8)?;
+            }
+            "plane" => {
+                let n = self.next_number(0, 3)?;
+                self.emit(0xF001u16 | (n as u16) << 8)?;
+            }
+            "audio" => self.emit(0xF002u16)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                let low = match text {
+                    "delay" => 0x15u16,
+                    "buzzer" => 0x18u16,
+                    _ => 0x3Au16
+                };
+                self.emit(0xF000u16 | (x as u16) << 8 | low)?;
+            }
+            "i" => self.i_statement()?,
+            "if" => {
+                let condition = self.condition()?;
+                let keyword = self.next_token()?;
+                match keyword.text.as_str() {
+                    "then" => self.emit_skip(&condition, false)?,
+                    "begin" => {
+                        self.emit_skip(&condition, true)?;
+                        let jump = self.here;
+                        self.emit(0x1000u16)?;
+                        self.blocks.push(Block::If { jump });
+                    }
+                    other => return Err(self.error(format!("Expected 'then' or 'begin', got '{}'", other)))
+                }
+            }
+            "else" => {
+                let jump = match self.blocks.pop() {
+                    Some(Block::If { jump }) => jump,
+                    _ => return Err(self.error(String::from("'else' without 'if ... begin'")))
+                };
+                let else_jump = self.here;
+                self.emit(0x1000u16)?;
+                self.patch_jump(jump)?;
+                self.blocks.push(Block::Else { jump: else_jump });
+            }
+            "end" => {
+                match self.blocks.pop() {
+                    Some(Block::If { jump }) | Some(Block::Else { jump }) => self.patch_jump(jump)?,
+                    _ => return Err(self.error(String::from("'end' without 'begin'")))
+                }
+            }
+            "loop" => {
+                let start = self.here;
+                self.blocks.push(Block::Loop { start, breaks: Vec::new() });
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_skip(&condition, true)?;
+                let jump = self.here;
+                self.emit(0x1000u16)?;
+                match self.blocks.iter_mut().rev().find(|block| matches!(block, Block::Loop { .. })) {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return Err(self.error(String::from("'while' outside of a loop")))
+                }
+            }
+            "again" => {
+                let (start, breaks) = match self.blocks.pop() {
+                    Some(Block::Loop { start, breaks }) => (start, breaks),
+                    _ => return Err(self.error(String::from("'again' without 'loop'")))
+                };
+                if start > 0x0FFF {
+                    return Err(self.error(format!("Loop at 0x{:04X} is out of reach of 'again'", start)));
+                }
+                self.emit(0x1000u16 | start as u16)?;
+                for jump in breaks {
+                    self.patch_jump(jump)?;
+                }
+            }
+            _ if classic_mnemonic(text) => self.classic_statement(&token)?,
+            // Any other name is a subroutine call.
+            _ if is_identifier(text) => self.emit_address(0x2000u16, &token)?,
+            _ => return Err(self.error(format!("Unexpected '{}'", text)))
+        }
+        return Ok(());
+    }
+
+    fn register_statement(&mut self, x: usize) -> Result<(), AsmError> {
+        let operator = self.next_token()?;
+        let source = self.next_token()?;
+        let rx = (x as u16) << 8;
+        let register = self.register(&source.text).map(|y| (y as u16) << 4);
+        let op = match (operator.text.as_str(), register) {
+            (":=", Some(ry)) => 0x8000u16 | rx | ry,
+            ("|=", Some(ry)) => 0x8001u16 | rx | ry,
+            ("&=", Some(ry)) => 0x8002u16 | rx | ry,
+            ("^=", Some(ry)) => 0x8003u16 | rx | ry,
+            ("+=", Some(ry)) => 0x8004u16 | rx | ry,
+            ("-=", Some(ry)) => 0x8005u16 | rx | ry,
+            (">>=", Some(ry)) => 0x8006u16 | rx | ry,
+            ("=-", Some(ry)) => 0x8007u16 | rx | ry,
+            ("<<=", Some(ry)) => 0x800Eu16 | rx | ry,
+            (":=", None) => match source.text.as_str() {
+                "delay" => 0xF007u16 | rx,
+                "key" => 0xF00Au16 | rx,
+                "random" => 0xC000u16 | rx | self.next_byte()? as u16,
+                _ => 0x6000u16 | rx | self.byte_value(&source)? as u16
+            },
+            ("+=", None) => 0x7000u16 | rx | self.byte_value(&source)? as u16,
+            ("-=", None) => 0x7000u16 | rx | (self.byte_value(&source)?.wrapping_neg()) as u16,
+            _ => return Err(self.error(format!("Unexpected '{}' after register", operator.text)))
+        };
+        return self.emit(op);
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let operator = self.next_token()?;
+        match operator.text.as_str() {
+            ":=" => {
+                let source = self.next_token()?;
+                match source.text.as_str() {
+                    "hex" => {
+                        let x = self.next_register()?;
+                        self.emit(0xF029u16 | (x as u16) << 8)?;
+                    }
+                    "bighex" => {
+                        let x = self.next_register()?;
+                        self.emit(0xF030u16 | (x as u16) << 8)?;
+                    }
+                    "long" => {
+                        let target = self.next_token()?;
+                        self.emit_long(&target)?;
+                    }
+                    _ => self.emit_address(0xA000u16, &source)?
+                }
+            }
+            "+=" => {
+                let x = self.next_register()?;
+                self.emit(0xF01Eu16 | (x as u16) << 8)?;
+            }
+            other => return Err(self.error(format!("Unexpected '{}' after i", other)))
+        }
+        return Ok(());
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.next_register()?;
+        let operator = self.next_token()?;
+        let condition = match operator.text.as_str() {
+            "key" => Condition::Key(x),
+            "-key" => Condition::NotKey(x),
+            "==" => Condition::Equal(x, self.operand()?),
+            "!=" => Condition::NotEqual(x, self.operand()?),
+            "<" | ">" | "<=" | ">=" => Condition::Compare(operator.text.clone(), x, self.operand()?),
+            other => return Err(self.error(format!("Unknown comparison '{}'", other)))
+        };
+        return Ok(condition);
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let token = self.next_token()?;
+        return match self.register(&token.text) {
+            Some(y) => Ok(Operand::Register(y)),
+            None => Ok(Operand::Byte(self.byte_value(&token)?))
+        };
+    }
+
+    // Emits code that skips the next instruction when the condition equals `skip_when`.
+    fn emit_skip(&mut self, condition: &Condition, skip_when: bool) -> Result<(), AsmError> {
+        let (equal, x, operand) = match condition {
+            Condition::Key(x) => return self.emit(if skip_when { 0xE09Eu16 } else { 0xE0A1u16 } | (*x as u16) << 8),
+            Condition::NotKey(x) => return self.emit(if skip_when { 0xE0A1u16 } else { 0xE09Eu16 } | (*x as u16) << 8),
+            Condition::Equal(x, operand) => (skip_when, *x, operand),
+            Condition::NotEqual(x, operand) => (!skip_when, *x, operand),
+            Condition::Compare(operator, x, operand) => {
+                // VF = operand - Vx or Vx - operand; the subtraction leaves VF = 1 when there is no borrow.
+                match operand {
+                    Operand::Register(y) => self.emit(0x8F00u16 | (*y as u16) << 4)?,
+                    Operand::Byte(kk) => self.emit(0x6F00u16 | *kk as u16)?
+                }
+                let one_means_true = operator == "<=" || operator == ">=";
+                if operator == ">" || operator == "<=" {
+                    self.emit(0x8F05u16 | (*x as u16) << 4)?;
+                } else {
+                    self.emit(0x8F07u16 | (*x as u16) << 4)?;
+                }
+                let vf = if one_means_true == skip_when { 1u16 } else { 0u16 };
+                return self.emit(0x3F00u16 | vf);
+            }
+        };
+        let rx = (x as u16) << 8;
+        let op = match (operand, equal) {
+            (Operand::Byte(kk), true) => 0x3000u16 | rx | *kk as u16,
+            (Operand::Byte(kk), false) => 0x4000u16 | rx | *kk as u16,
+            (Operand::Register(y), true) => 0x5000u16 | rx | (*y as u16) << 4,
+            (Operand::Register(y), false) => 0x9000u16 | rx | (*y as u16) << 4
+        };
+        return self.emit(op);
+    }
+
+    fn classic_statement(&mut self, mnemonic: &Token) -> Result<(), AsmError> {
+        let mut operands = Vec::new();
+        while self.tokens.front().is_some_and(|token| token.line == mnemonic.line) {
+            operands.push(self.tokens.pop_front().unwrap());
+        }
+        let name = &mnemonic.text;
+        let texts: Vec<String> = operands.iter().map(|token| token.text.to_uppercase()).collect();
+        let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+        let registers: Vec<Option<usize>> = operands.iter().map(|token| self.register(&token.text)).collect();
+        let rx = registers.first().copied().flatten().map_or(0u16, |x| (x as u16) << 8);
+        let ry = registers.get(1).copied().flatten().map_or(0u16, |y| (y as u16) << 4);
+
+        let op = match (name.as_str(), texts.as_slice(), registers.as_slice()) {
+            ("CLS", [], _) => 0x00E0u16,
+            ("RET", [], _) => 0x00EEu16,
+            ("SCR", [], _) => 0x00FBu16,
+            ("SCL", [], _) => 0x00FCu16,
+            ("EXIT", [], _) => 0x00FDu16,
+            ("LOW", [], _) => 0x00FEu16,
+            ("HIGH", [], _) => 0x00FFu16,
+            ("AUDIO", [], _) => 0xF002u16,
+            ("SCD", [_], _) => 0x00C0u16 | self.nibble(&operands[0])?,
+            ("SCU", [_], _) => 0x00D0u16 | self.nibble(&operands[0])?,
+            ("PLANE", [_], _) => 0xF001u16 | self.nibble(&operands[0])? << 8,
+            ("SYS", [_], _) => return self.emit_address(0x0000u16, &operands[0]),
+            ("JP", ["V0", _], _) => return self.emit_address(0xB000u16, &operands[1]),
+            ("JP", [_], _) => return self.emit_address(0x1000u16, &operands[0]),
+            ("CALL", [_], _) => return self.emit_address(0x2000u16, &operands[0]),
+            ("SE", [_, _], [Some(_), Some(_)]) => 0x5000u16 | rx | ry,
+            ("SE", [_, _], [Some(_), None]) => 0x3000u16 | rx | self.byte_value(&operands[1])? as u16,
+            ("SNE", [_, _], [Some(_), Some(_)]) => 0x9000u16 | rx | ry,
+            ("SNE", [_, _], [Some(_), None]) => 0x4000u16 | rx | self.byte_value(&operands[1])? as u16,
+            ("OR", [_, _], [Some(_), Some(_)]) => 0x8001u16 | rx | ry,
+            ("AND", [_, _], [Some(_), Some(_)]) => 0x8002u16 | rx | ry,
+            ("XOR", [_, _], [Some(_), Some(_)]) => 0x8003u16 | rx | ry,
+            ("SUB", [_, _], [Some(_), Some(_)]) => 0x8005u16 | rx | ry,
+            ("SUBN", [_, _], [Some(_), Some(_)]) => 0x8007u16 | rx | ry,
+            // The second register of the shifts is optional and defaults to Vx.
+            ("SHR", [_], [Some(_)]) => 0x8006u16 | rx | rx >> 4,
+            ("SHR", [_, _], [Some(_), Some(_)]) => 0x8006u16 | rx | ry,
+            ("SHL", [_], [Some(_)]) => 0x800Eu16 | rx | rx >> 4,
+            ("SHL", [_, _], [Some(_), Some(_)]) => 0x800Eu16 | rx | ry,
+            ("ADD", ["I", _], [_, Some(x)]) => 0xF01Eu16 | (*x as u16) << 8,
+            ("ADD", [_, _], [Some(_), Some(_)]) => 0x8004u16 | rx | ry,
+            ("ADD", [_, _], [Some(_), None]) => 0x7000u16 | rx | self.byte_value(&operands[1])? as u16,
+            ("RND", [_, _], [Some(_), None]) => 0xC000u16 | rx | self.byte_value(&operands[1])? as u16,
+            ("DRW", [_, _, _], [Some(_), Some(_), None]) => 0xD000u16 | rx | ry | self.nibble(&operands[2])?,
+            ("SKP", [_], [Some(_)]) => 0xE09Eu16 | rx,
+            ("SKNP", [_], [Some(_)]) => 0xE0A1u16 | rx,
+            ("PITCH", [_], [Some(_)]) => 0xF03Au16 | rx,
+            ("LD", ["I", "LONG", _], _) => return self.emit_long(&operands[2]),
+            ("LD", ["I", _], _) => return self.emit_address(0xA000u16, &operands[1]),
+            ("LD", ["DT", _], [_, Some(x)]) => 0xF015u16 | (*x as u16) << 8,
+            ("LD", ["ST", _], [_, Some(x)]) => 0xF018u16 | (*x as u16) << 8,
+            ("LD", ["F", _], [_, Some(x)]) => 0xF029u16 | (*x as u16) << 8,
+            ("LD", ["HF", _], [_, Some(x)]) => 0xF030u16 | (*x as u16) << 8,
+            ("LD", ["B", _], [_, Some(x)]) => 0xF033u16 | (*x as u16) << 8,
+            ("LD", ["R", _], [_, Some(x)]) => 0xF075u16 | (*x as u16) << 8,
+            ("LD", ["[I]", _], _) => self.classic_range(&operands[1].text, 0xF055u16, 0x5002u16)?,
+            ("LD", [_, "[I]"], _) => self.classic_range(&operands[0].text, 0xF065u16, 0x5003u16)?,
+            ("LD", [_, "DT"], [Some(_), _]) => 0xF007u16 | rx,
+            ("LD", [_, "K"], [Some(_), _]) => 0xF00Au16 | rx,
+            ("LD", [_, "R"], [Some(_), _]) => 0xF085u16 | rx,
+            ("LD", [_, _], [Some(_), Some(_)]) => 0x8000u16 | rx | ry,
+            ("LD", [_, _], [Some(_), None]) => 0x6000u16 | rx | self.byte_value(&operands[1])? as u16,
+            ("DB", [..], _) if !operands.is_empty() => {
+                for operand in operands.iter() {
+                    let value = self.byte_value(operand)?;
+                    self.emit_byte(value)?;
+                }
+                return Ok(());
+            }
+            ("DW", [_], _) => {
+                let value = self.value(&operands[0], 0, 0xFFFF)?;
+                return self.emit(value as u16);
+            }
+            _ => {
+                let line: Vec<&str> = operands.iter().map(|token| token.text.as_str()).collect();
+                return Err(self.error(format!("Invalid operands for {}: '{}'", name, line.join(", "))));
+            }
+        };
+        return self.emit(op);
+    }
+
+    // "Vx" for the Fx55/Fx65 form, or "Vx-Vy" for the XO-CHIP 5xy2/5xy3 form.
+    fn classic_range(&self, text: &str, single: u16, range: u16) -> Result<u16, AsmError> {
+        if let Some(x) = self.register(text) {
+            return Ok(single | (x as u16) << 8);
+        }
+        let registers: Vec<Option<usize>> = text.split('-').map(|part| self.register(part)).collect();
+        return match registers.as_slice() {
+            [Some(x), Some(y)] => Ok(range | (*x as u16) << 8 | (*y as u16) << 4),
+            _ => Err(self.error(format!("Expected a register or register range, got '{}'", text)))
+        };
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), AsmError> {
+        if !is_identifier(name) {
+            return Err(self.error(format!("Invalid label name '{}'", name)));
+        }
+        if self.labels.contains_key(name) {
+            return Err(self.error(format!("Label '{}' is already defined", name)));
+        }
+        self.labels.insert(String::from(name), self.here as u16);
+        return Ok(());
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.next_identifier()?;
+        let mut parameters = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            if token.text == "{" {
+                break;
+            }
+            parameters.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = match self.tokens.pop_front() {
+                Some(token) => token,
+                None => return Err(self.error(format!("Macro '{}' is never closed", name)))
+            };
+            if token.text == "{" {
+                depth += 1;
+            } else if token.text == "}" {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        return Ok(());
+    }
+
+    fn expand_macro(&mut self, invocation: &Token) -> Result<(), AsmError> {
+        // A macro that ends up invoking itself would otherwise expand forever.
+        if invocation.depth >= MAX_MACRO_DEPTH {
+            return Err(self.error(format!("Macro '{}' is nested more than {} deep", invocation.text, MAX_MACRO_DEPTH)));
+        }
+        let definition = &self.macros[&invocation.text];
+        let mut arguments = HashMap::new();
+        for parameter in definition.parameters.iter() {
+            match self.tokens.pop_front() {
+                Some(argument) => arguments.insert(parameter.clone(), argument.text),
+                None => return Err(self.error(format!("Macro '{}' needs {} arguments", invocation.text, definition.parameters.len())))
+            };
+        }
+        for token in definition.body.iter().rev() {
+            let text = arguments.get(&token.text).cloned().unwrap_or_else(|| token.text.clone());
+            self.tokens.push_front(Token { text, line: token.line, depth: invocation.depth + 1 });
+        }
+        return Ok(());
+    }
+
+    fn register(&self, text: &str) -> Option<usize> {
+        if let Some(register) = self.aliases.get(text) {
+            return Some(*register);
+        }
+        let mut chars = text.chars();
+        return match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|x| x as usize),
+            _ => None
+        };
+    }
+
+    fn constant_value(&self, text: &str) -> Option<i64> {
+        return parse_number(text)
+            .or_else(|| self.constants.get(text).copied())
+            .or_else(|| self.labels.get(text).map(|address| *address as i64));
+    }
+
+    fn value(&self, token: &Token, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = match self.constant_value(&token.text) {
+            Some(value) => value,
+            None => return Err(self.error(format!("Expected a value, got '{}'", token.text)))
+        };
+        if value < min || value > max {
+            return Err(self.error(format!("Value {} is out of range {}..={}", token.text, min, max)));
+        }
+        return Ok(value);
+    }
+
+    // Bytes may be written signed, so -1 is 0xFF.
+    fn byte_value(&self, token: &Token) -> Result<u8, AsmError> {
+        return Ok(self.value(token, -128, 255)? as u8);
+    }
+
+    fn nibble(&self, token: &Token) -> Result<u16, AsmError> {
+        return Ok(self.value(token, 0, 15)? as u16);
+    }
+
+    fn next_token(&mut self) -> Result<Token, AsmError> {
+        return match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token)
+            }
+            None => Err(self.error(String::from("Unexpected end of source")))
+        };
+    }
+
+    fn next_identifier(&mut self) -> Result<String, AsmError> {
+        let token = self.next_token()?;
+        if !is_identifier(&token.text) {
+            return Err(self.error(format!("Expected a name, got '{}'", token.text)));
+        }
+        return Ok(token.text);
+    }
+
+    fn next_register(&mut self) -> Result<usize, AsmError> {
+        let token = self.next_token()?;
+        return match self.register(&token.text) {
+            Some(register) => Ok(register),
+            None => Err(self.error(format!("Expected a register, got '{}'", token.text)))
+        };
+    }
+
+    fn next_number(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        let token = self.next_token()?;
+        return self.value(&token, min, max);
+    }
+
+    fn next_byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next_token()?;
+        return self.byte_value(&token);
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next_token()?;
+        if token.text != text {
+            return Err(self.error(format!("Expected '{}', got '{}'", text, token.text)));
+        }
+        return Ok(());
+    }
+
+    fn error(&self, message: String) -> AsmError {
+        return AsmError { line: self.line, message };
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= MEMORY_END {
+            return Err(self.error(String::from("Program does not fit in memory")));
+        }
+        let index = self.here - PROGRAM_START;
+        if self.rom.len() <= index {
+            self.rom.resize(index + 1, 0u8);
+        }
+        self.rom[index] = byte;
+        self.here += 1;
+        return Ok(());
+    }
+
+    fn emit_byte_value(&mut self, value: i64) -> Result<(), AsmError> {
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!("Byte {} is out of range", value)));
+        }
+        return self.emit_byte(value as u8);
+    }
+
+    fn emit(&mut self, op: u16) -> Result<(), AsmError> {
+        self.emit_byte((op >> 8) as u8)?;
+        return self.emit_byte(op as u8);
+    }
+
+    // Emits an instruction with a 12-bit address, resolving labels that are defined later.
+    fn emit_address(&mut self, op: u16, target: &Token) -> Result<(), AsmError> {
+        if let Some(address) = self.constant_value(&target.text) {
+            if !(0..=0x0FFF).contains(&address) {
+                return Err(self.error(format!("Address {} is out of range", target.text)));
+            }
+            return self.emit(op | address as u16);
+        }
+        if !is_identifier(&target.text) {
+            return Err(self.error(format!("Expected an address, got '{}'", target.text)));
+        }
+        self.fixups.push(Fixup { address: self.here, name: target.text.clone(), kind: FixupKind::Address, line: target.line });
+        return self.emit(op);
+    }
+
+    fn emit_long(&mut self, target: &Token) -> Result<(), AsmError> {
+        self.emit(0xF000u16)?;
+        if let Some(address) = self.constant_value(&target.text) {
+            if !(0..=0xFFFF).contains(&address) {
+                return Err(self.error(format!("Address {} is out of range", target.text)));
+            }
+            return self.emit(address as u16);
+        }
+        if !is_identifier(&target.text) {
+            return Err(self.error(format!("Expected an address, got '{}'", target.text)));
+        }
+        self.fixups.push(Fixup { address: self.here, name: target.text.clone(), kind: FixupKind::Long, line: target.line });
+        return self.emit(0x0000u16);
+    }
+
+    // Points the placeholder jump at `jump` to the current address.
+    fn patch_jump(&mut self, jump: usize) -> Result<(), AsmError> {
+        if self.here > 0x0FFF {
+            return Err(self.error(format!("Jump target 0x{:04X} is out of reach", self.here)));
+        }
+        let index = jump - PROGRAM_START;
+        self.rom[index] = 0x10u8 | ((self.here >> 8) & 0x0F) as u8;
+        self.rom[index + 1] = self.here as u8;
+        return Ok(());
+    }
+}
+
+fn classic_mnemonic(text: &str) -> bool {
+    const MNEMONICS: [&str; 30] = [
+        "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN",
+        "SHL", "RND", "DRW", "SKP", "SKNP", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "PLANE", "PITCH", "AUDIO"
+    ];
+    return MNEMONICS.contains(&text) || text == "DB" || text == "DW";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        return rom.chunks(2).map(|pair| ((pair[0] as u16) << 8) | (*pair.get(1).unwrap_or(&0) as u16)).collect();
+    }
+
+    #[test]
+    fn octo_statements() {
+        let source = "
+            : main
+              clear
+              v0 := 5  v1 += v0  v2 := random 0x0F
+              i := digits  sprite v0 v1 5
+              delay := v0  v3 := key
+              save v1 - v3
+              loop again
+            : digits
+              0xF0 0x90 0b11110000
+        ";
+        let assembled = assemble(source).unwrap();
+        assert_eq!(
+            words(&assembled.rom),
+            vec![0x00E0, 0x6005, 0x8104, 0xC20F, 0xA214, 0xD015, 0xF015, 0xF30A, 0x5132, 0x1212, 0xF090, 0xF000]
+        );
+        assert_eq!(assembled.labels["digits"], 0x214);
+    }
+
+    #[test]
+    fn jumps_to_main_when_it_is_not_first() {
+        let assembled = assemble(": sub return\n: main sub").unwrap();
+        assert_eq!(words(&assembled.rom), vec![0x1204, 0x00EE, 0x2202]);
+        let assembled = assemble("sub: return\nmain: sub").unwrap();
+        assert_eq!(words(&assembled.rom), vec![0x1204, 0x00EE, 0x2202]);
+        let assembled = assemble("main: jump main").unwrap();
+        assert_eq!(words(&assembled.rom), vec![0x1200]);
+    }
+
+    #[test]
+    fn constants_aliases_and_macros() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            :macro move reg amount { reg += amount }
+            move x SPEED
+        ";
+        assert_eq!(words(&assemble(source).unwrap().rom), vec![0x7403]);
+    }
+
+    #[test]
+    fn control_flow() {
+        let source = "
+            if v0 == 1 then v1 := 2
+            if v0 key begin v1 := 3 else v1 := 4 end
+            loop
+              while v2 != v3
+              v2 += 1
+            again
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap().rom),
+            vec![0x4001, 0x6102, 0xE09E, 0x120C, 0x6103, 0x120E, 0x6104, 0x9230, 0x1216, 0x7201, 0x120E]
+        );
+    }
+
+    #[test]
+    fn comparisons_go_through_vf() {
+        assert_eq!(words(&assemble("if v1 > 5 then v0 := 0").unwrap().rom), vec![0x6F05, 0x8F15, 0x3F01, 0x6000]);
+        assert_eq!(words(&assemble("if v1 >= v2 then v0 := 0").unwrap().rom), vec![0x8F20, 0x8F17, 0x3F00, 0x6000]);
+    }
+
+    #[test]
+    fn classic_mnemonics() {
+        let source = "
+            start: LD V1, 0x05
+                   DRW V0, V1, 5
+                   LD [I], V1-V3
+                   LD V2, [I]
+                   SHR V4
+                   ADD I, V2
+                   LD I, LONG data
+                   JP start
+            data:  DB 0x3C, 0x42
+        ";
+        assert_eq!(
+            words(&assemble(source).unwrap().rom),
+            vec![0x6105, 0xD015, 0x5132, 0xF265, 0x8446, 0xF21E, 0xF000, 0x0212, 0x1200, 0x3C42]
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_lines() {
+        assert_eq!(
+            assemble("clear\njump nowhere").err().unwrap(),
+            AsmError { line: 2, message: String::from("Undefined name 'nowhere'") }
+        );
+        assert_eq!(assemble("v0 := 300").err().unwrap().line, 1);
+        assert!(assemble("loop").is_err());
+        assert_eq!(
+            assemble(":macro boom { boom }\nboom").err().unwrap(),
+            AsmError { line: 1, message: String::from("Macro 'boom' is nested more than 64 deep") }
+        );
+        assert_eq!(
+            assemble(":org 0x1400\nloop again").err().unwrap().message,
+            "Loop at 0x1400 is out of reach of 'again'"
+        );
+        assert_eq!(
+            assemble("if v0 key begin\n:org 0x1400\nend").err().unwrap().message,
+            "Jump target 0x1400 is out of reach"
+        );
+    }
+
+    #[test]
+    fn symbol_map_is_sorted_by_address() {
+        let assembled = assemble(": main jump b\n: b jump main").unwrap();
+        assert_eq!(assembled.symbol_map(), "0x0200 main\n0x0202 b\n");
+    }
+}
@@ -0,0 +1,122 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use sdl2::Sdl;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use rip_8::sound::Sound;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        return match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" | "saw" => Some(Waveform::Sawtooth),
+            _ => None
+        };
+    }
+
+    // Amplitude in -1..=1 at a phase in 0..1.
+    fn sample(&self, phase: f32) -> f32 {
+        return match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0
+        };
+    }
+}
+
+pub struct AudioSettings {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+    pub muted: bool
+}
+
+// Plays while the sound timer is non-zero: the XO-CHIP pattern buffer if the ROM loaded one, otherwise a tone.
+pub struct Beeper {
+    pub settings: AudioSettings,
+    st: Arc<Mutex<u8>>,
+    sound: Arc<Mutex<Sound>>,
+    sample_rate: f32,
+    phase: f32
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let playing = *self.st.lock().unwrap() > 0 && !self.settings.muted;
+        if !playing {
+            self.phase = 0.0;
+            for sample in out.iter_mut() {
+                *sample = 0.0;
+            }
+            return;
+        }
+
+        let sound = self.sound.lock().unwrap();
+        let volume = self.settings.volume;
+        match sound.pattern {
+            Some(pattern) => {
+                // The phase counts pattern bits, wrapping after all 128.
+                let step = sound.playback_rate() / self.sample_rate;
+                for sample in out.iter_mut() {
+                    let bit = self.phase as usize;
+                    let on = (pattern[bit / 8] >> (7 - bit % 8)) & 0b1u8 == 0b1u8;
+                    *sample = if on { volume } else { -volume };
+                    self.phase = (self.phase + step) % 128.0;
+                }
+            }
+            None => {
+                let step = self.settings.frequency / self.sample_rate;
+                for sample in out.iter_mut() {
+                    *sample = self.settings.waveform.sample(self.phase) * volume;
+                    self.phase = (self.phase + step) % 1.0;
+                }
+            }
+        }
+    }
+}
+
+// None when the host has no audio driver or device; the emulator then runs silently.
+pub fn open_beeper(
+    sdl_context: &Sdl,
+    settings: AudioSettings,
+    st: Arc<Mutex<u8>>,
+    sound: Arc<Mutex<Sound>>
+) -> Option<AudioDevice<Beeper>> {
+    let audio_subsystem = match sdl_context.audio() {
+        Ok(audio_subsystem) => audio_subsystem,
+        Err(error) => {
+            eprintln!("rip_8: No audio, running without sound: {}", error);
+            return None;
+        }
+    };
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44_100),
+        channels: Some(1),
+        samples: Some(512)
+    };
+    let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+        return Beeper { settings, st, sound, sample_rate: spec.freq as f32, phase: 0.0 };
+    });
+    return match device {
+        Ok(device) => {
+            device.resume();
+            Some(device)
+        }
+        Err(error) => {
+            eprintln!("rip_8: Could not open an audio device, running without sound: {}", error);
+            None
+        }
+    };
+}
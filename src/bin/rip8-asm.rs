@@ -0,0 +1,91 @@
+#![allow(clippy::needless_return)]
+
+use rip_8::assembler::assemble;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "\
+Usage: rip8-asm [OPTIONS] <SOURCE>
+
+Assembles Octo source, or classic mnemonics such as 'LD V0, 0x05', into a ROM that loads at 0x200.
+
+Options:
+    -o, --output <PATH>   Where to write the ROM (default: SOURCE with a .ch8 extension)
+    --symbols <PATH>      Also write a map of label addresses
+    -h, --help            Print this message
+";
+
+struct Options {
+    source_path: PathBuf,
+    output_path: PathBuf,
+    symbols_path: Option<PathBuf>
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rip8-asm: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let source = match fs::read_to_string(&options.source_path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("rip8-asm: Could not read {}: {}", options.source_path.display(), error);
+            process::exit(1);
+        }
+    };
+    let assembled = match assemble(&source) {
+        Ok(assembled) => assembled,
+        Err(error) => {
+            eprintln!("{}:{}", options.source_path.display(), error);
+            process::exit(1);
+        }
+    };
+    if let Err(error) = fs::write(&options.output_path, &assembled.rom) {
+        eprintln!("rip8-asm: Could not write {}: {}", options.output_path.display(), error);
+        process::exit(1);
+    }
+    if let Some(symbols_path) = &options.symbols_path {
+        if let Err(error) = fs::write(symbols_path, assembled.symbol_map()) {
+            eprintln!("rip8-asm: Could not write {}: {}", symbols_path.display(), error);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut source_path = None;
+    let mut output_path = None;
+    let mut symbols_path = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                output_path = Some(PathBuf::from(value));
+            }
+            "--symbols" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                symbols_path = Some(PathBuf::from(value));
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+            _ if source_path.is_some() => return Err(format!("Unexpected argument '{}'", arg)),
+            _ => source_path = Some(PathBuf::from(arg))
+        }
+    }
+
+    let source_path: PathBuf = source_path.ok_or_else(|| String::from("No source file given"))?;
+    let output_path = output_path.unwrap_or_else(|| source_path.with_extension("ch8"));
+    return Ok(Options { source_path, output_path, symbols_path });
+}
@@ -0,0 +1,49 @@
+#![allow(clippy::needless_return)]
+
+use rip_8::disasm::listing;
+use rip_8::rom::PROGRAM_START;
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+Usage: rip8-disasm <ROM>
+
+Prints an annotated listing of a ROM. Code is found by following jumps, calls and skips from 0x200;
+everything else is shown as data.
+
+Options:
+    -h, --help          Print this message
+";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let rom_path = match args.as_slice() {
+        [path] if !path.starts_with('-') => path,
+        _ => {
+            eprintln!("rip8-disasm: Expected exactly one ROM\n\n{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let rom = match fs::read(rom_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("rip8-disasm: Could not read ROM {}: {}", rom_path, error);
+            process::exit(1);
+        }
+    };
+    // Anything past 0xFFFF is not addressable, even with XO-CHIP's 64K.
+    let max_size = 0x10000 - PROGRAM_START;
+    if rom.len() > max_size {
+        eprintln!(
+            "rip8-disasm: ROM is {} bytes but at most {} bytes fit in memory after 0x{:03X}",
+            rom.len(), max_size, PROGRAM_START
+        );
+        process::exit(1);
+    }
+    print!("{}", listing(&rom, PROGRAM_START as u16));
+}
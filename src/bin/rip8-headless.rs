@@ -0,0 +1,230 @@
+#![allow(clippy::needless_return)]
+
+use rip_8::dump::{display_ascii, display_hash, memory_hex, registers};
+use rip_8::hooks::Hooks;
+use rip_8::key_event::KeyEvent;
+use rip_8::machine::Machine;
+use rip_8::movie::{Movie, MovieHooks, MovieMode};
+use rip_8::quirks::{Quirks, PROFILE_NAMES};
+use rip_8::rom::load_rom_data;
+use rip_8::save_state::load_state;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: rip8-headless [OPTIONS] <ROM>
+
+Runs a ROM without a window and prints the final display, registers and memory. Exits with status 3
+if the CPU faults.
+
+Options:
+    --frames <N>        Run N frames of 1/60 s (default: 600)
+    --instructions <N>  Run N instructions instead of a number of frames
+    --ipf <N>           Instructions per frame (default: 8)
+    --seed <N>          Seed for the random number generator (default: 0)
+    --quirks <NAME>     Interpreter to emulate: vip, chip48, schip, xochip or modern (default: modern)
+    --key-wait <WHEN>   Finish Fx0A on key press or release (default: set by --quirks)
+    --png <PATH>        Write the display to a PNG instead of printing it
+    --state <PATH>      Start from a save state instead of the beginning of the ROM, with the quirks
+                        and random number generator saved in it
+    --play <PATH>       Replay the input of a movie, using its seed, quirks and instructions per frame;
+                        cannot be combined with --state
+    -h, --help          Print this message
+";
+
+// Shades for each combination of the two XO-CHIP bitplanes.
+const GRAY_LEVELS: [u8; 4] = [0x00, 0xFF, 0xAA, 0x55];
+
+struct Options {
+    rom_path: PathBuf,
+    instructions: u64,
+    instructions_per_frame: u64,
+    seed: u64,
+    quirks: Quirks,
+    png_path: Option<PathBuf>,
+    state_path: Option<PathBuf>,
+    movie: Option<Movie>
+}
+
+// No input is ever available outside a movie, so Fx0A waits forever.
+struct HeadlessHooks;
+
+impl Hooks for HeadlessHooks {
+    fn poll_key(&mut self) -> Option<KeyEvent> {
+        return None;
+    }
+
+    fn display_updated(&mut self) {}
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let options = match parse_options(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rip8-headless: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom_data = match fs::read(&options.rom_path) {
+        Ok(rom_data) => rom_data,
+        Err(error) => {
+            eprintln!("rip8-headless: Could not load ROM {}: {}", options.rom_path.display(), error);
+            process::exit(1);
+        }
+    };
+    if let Some(movie) = options.movie.as_ref().filter(|movie| !movie.matches_rom(&rom_data)) {
+        eprintln!("rip8-headless: The movie was recorded with a different ROM (SHA-1 {})", movie.rom_hash);
+        process::exit(1);
+    }
+    let mut machine = Machine::init();
+    machine.seed_rng(options.seed);
+    machine.quirks = options.quirks;
+    if let Err(error) = load_rom_data(&mut machine, &rom_data) {
+        eprintln!("rip8-headless: Could not load ROM {}: {}", options.rom_path.display(), error);
+        process::exit(1);
+    }
+    if let Some(state_path) = &options.state_path {
+        if let Err(error) = load_state(&mut machine, state_path) {
+            eprintln!("rip8-headless: Could not load state {}: {}", state_path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    let mode = match options.movie {
+        Some(movie) => MovieMode::Playing(movie.events.into_iter().collect()),
+        None => MovieMode::Off
+    };
+    let mut hooks = MovieHooks::new(HeadlessHooks, mode);
+    let mut fault = None;
+    for n in 1..=options.instructions {
+        if machine.exited {
+            break;
+        }
+        hooks.instruction = n - 1;
+        if let Err(error) = machine.step(&mut hooks) {
+            fault = Some(error);
+            break;
+        }
+        // Virtual 60 Hz timer.
+        if n % options.instructions_per_frame == 0 {
+            machine.tick_timers();
+        }
+    }
+
+    match &options.png_path {
+        Some(png_path) => {
+            if let Err(error) = write_png(&machine, png_path) {
+                eprintln!("rip8-headless: Could not write {}: {}", png_path.display(), error);
+                process::exit(1);
+            }
+        }
+        None => println!("{}", display_ascii(&machine))
+    }
+    println!("Display hash: {:016X}", display_hash(&machine));
+    print!("{}\n{}", registers(&machine), memory_hex(&machine));
+    if let Some(fault) = fault {
+        eprintln!("rip8-headless: {}", fault);
+        process::exit(3);
+    }
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut frames = 600u64;
+    let mut instructions = None;
+    let mut instructions_per_frame = 8u64;
+    let mut seed = 0u64;
+    let mut quirks = Quirks::default();
+    let mut key_wait_release = None;
+    let mut png_path = None;
+    let mut state_path = None;
+    let mut movie = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = parse_number(&arg, args.next())?,
+            "--instructions" => instructions = Some(parse_number(&arg, args.next())?),
+            "--ipf" => instructions_per_frame = parse_number(&arg, args.next())?,
+            "--seed" => seed = parse_number(&arg, args.next())?,
+            "--quirks" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                quirks = Quirks::from_name(&value).ok_or_else(|| format!(
+                    "Unknown quirk profile '{}', expected one of: {}",
+                    value, PROFILE_NAMES.join(", ")
+                ))?;
+            }
+            "--key-wait" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                key_wait_release = match value.as_str() {
+                    "press" => Some(false),
+                    "release" => Some(true),
+                    _ => return Err(format!("Option '{}' expects 'press' or 'release', got '{}'", arg, value))
+                };
+            }
+            "--png" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                png_path = Some(PathBuf::from(value));
+            }
+            "--play" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                movie = Some(Movie::load(Path::new(&value)).map_err(|error| format!("Could not load movie {}: {}", value, error))?);
+            }
+            "--state" => {
+                let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                state_path = Some(PathBuf::from(value));
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+            _ if rom_path.is_some() => return Err(format!("Unexpected argument '{}'", arg)),
+            _ => rom_path = Some(PathBuf::from(arg))
+        }
+    }
+
+    if instructions_per_frame == 0 {
+        return Err(String::from("Option '--ipf' must be at least 1"));
+    }
+    // A movie starts from a reset machine, so a loaded state would throw it out of sync.
+    if state_path.is_some() && movie.is_some() {
+        return Err(String::from("Options '--state' and '--play' cannot be used together"));
+    }
+    let rom_path = rom_path.ok_or_else(|| String::from("No ROM given"))?;
+    if let Some(key_wait_release) = key_wait_release {
+        quirks.key_wait_release = key_wait_release;
+    }
+    // A movie only replays correctly with the timing and quirks it was recorded with.
+    if let Some(movie) = &movie {
+        seed = movie.seed;
+        instructions_per_frame = movie.instructions_per_frame as u64;
+        quirks = movie.quirks;
+    }
+    let instructions = instructions.unwrap_or(frames * instructions_per_frame);
+    return Ok(Options { rom_path, instructions, instructions_per_frame, seed, quirks, png_path, state_path, movie });
+}
+
+fn parse_number(option: &str, value: Option<String>) -> Result<u64, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return value.parse::<u64>()
+        .map_err(|_| format!("Option '{}' expects a number, got '{}'", option, value));
+}
+
+fn write_png(machine: &Machine, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let display = machine.display.lock().unwrap();
+    let (width, height) = (display.width(), display.height());
+    let pixels: Vec<u8> = display.rows().iter().map(|&pixel| GRAY_LEVELS[pixel as usize]).collect();
+
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    return Ok(());
+}
@@ -0,0 +1,86 @@
+#![allow(clippy::needless_return)]
+
+use rip_8::keymap::{KeyConfig, key_config_path};
+use rip_8::rom_database::load_rom_database;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: rip8-keys [OPTIONS] [ROM]
+
+Prints which host keys and controller buttons are mapped to each CHIP-8 key, laid out like the CHIP-8
+keypad. With a ROM, the keys from its ROM database entry and the overrides for it are included.
+
+Options:
+    --keys <PATH>       Keyboard and controller mapping file (default: keys.cfg in the configuration directory)
+    -h, --help          Print this message
+";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let mut given_path = None;
+    let mut rom_path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keys" => match args.next() {
+                Some(value) => given_path = Some(PathBuf::from(value)),
+                None => usage_error(&format!("Option '{}' needs a value", arg))
+            },
+            _ if arg.starts_with('-') => usage_error(&format!("Unknown option '{}'", arg)),
+            _ if rom_path.is_some() => usage_error(&format!("Unexpected argument '{}'", arg)),
+            _ => rom_path = Some(PathBuf::from(arg))
+        }
+    }
+
+    let path = key_config_path(given_path.as_deref());
+    let config = match &path {
+        Some(path) => match KeyConfig::load(path) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!("rip8-keys: Could not load key mapping {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        },
+        None => KeyConfig::default()
+    };
+    let rom_name = rom_path.as_deref().and_then(Path::file_name).and_then(|name| name.to_str());
+    let roles = match &rom_path {
+        Some(rom_path) => {
+            let rom_data = fs::read(rom_path).unwrap_or_else(|error| {
+                eprintln!("rip8-keys: Could not load ROM {}: {}", rom_path.display(), error);
+                process::exit(1);
+            });
+            let rom_database = load_rom_database().unwrap_or_else(|message| {
+                eprintln!("rip8-keys: {}", message);
+                process::exit(1);
+            });
+            rom_database.lookup(&rom_data).map(|info| info.keys.clone()).unwrap_or_default()
+        }
+        None => Vec::new()
+    };
+    let bindings = config.bindings(rom_name, &roles).unwrap_or_else(|error| {
+        eprintln!("rip8-keys: Invalid key mapping: {}", error);
+        process::exit(1);
+    });
+    match &path {
+        Some(path) => print!("Key mapping from {}", path.display()),
+        None => print!("Default key mapping")
+    }
+    match rom_name {
+        Some(rom_name) => println!(" for {}", rom_name),
+        None => println!()
+    }
+    print!("\n{}", bindings.describe());
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("rip8-keys: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
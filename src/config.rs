@@ -0,0 +1,10 @@
+use std::env;
+use std::path::PathBuf;
+
+// Where Rip8 keeps its settings: $XDG_CONFIG_HOME/rip8, %APPDATA%\rip8 or ~/.config/rip8.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    return Some(base.join("rip8"));
+}
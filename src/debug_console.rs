@@ -0,0 +1,60 @@
+use std::io::{self, BufRead, Write};
+use rip_8::debugger::{Command, Debugger, HELP, disassembly_around, parse_command};
+use rip_8::dump::registers;
+use rip_8::machine::Machine;
+
+pub enum ConsoleAction {
+    Step(u32),
+    Continue,
+    Quit
+}
+
+// Prints the machine state, then reads debugger commands from the terminal until one resumes execution.
+pub fn run_console(machine: &mut Machine, debugger: &mut Debugger) -> ConsoleAction {
+    print!("{}{}", registers(machine), disassembly_around(machine, machine.pc, 4));
+    let stdin = io::stdin();
+    loop {
+        print!("(rip8) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            // End of input; keep running without the console.
+            debugger.resume();
+            return ConsoleAction::Continue;
+        }
+        match parse_command(&line) {
+            Ok(Command::Step(n)) => return ConsoleAction::Step(n.max(1)),
+            Ok(Command::Continue) => {
+                debugger.resume();
+                return ConsoleAction::Continue;
+            }
+            Ok(Command::Quit) => return ConsoleAction::Quit,
+            Ok(Command::Break(address)) => debugger.breakpoints.push(address),
+            Ok(Command::BreakOpcode(pattern)) => debugger.opcode_breakpoints.push(pattern),
+            Ok(Command::Watch(start, end)) => debugger.memory_watchpoints.push((start, end)),
+            Ok(Command::WatchI) => debugger.watch_i = true,
+            Ok(Command::List) => print!("{}", debugger.list()),
+            Ok(Command::Delete) => debugger.clear(),
+            Ok(Command::Registers) => {
+                print!("{}{}", registers(machine), disassembly_around(machine, machine.pc, 4));
+            }
+            Ok(Command::Memory(address, length)) => print_memory(machine, address, length),
+            Ok(Command::Disassemble(address)) => {
+                print!("{}", disassembly_around(machine, address.unwrap_or(machine.pc), 8));
+            }
+            Ok(Command::Help) => print!("{}", HELP),
+            Err(message) => println!("{}", message)
+        }
+    }
+}
+
+fn print_memory(machine: &Machine, address: u16, length: u16) {
+    let end = (address as usize + length as usize).min(machine.memory.len());
+    for row in (address as usize..end).step_by(16) {
+        let bytes: Vec<String> = machine.memory[row..end.min(row + 16)].iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        println!("{:04X}: {}", row, bytes.join(" "));
+    }
+}
+
@@ -0,0 +1,314 @@
+use crate::instruction::{decode, Instruction};
+use crate::machine::Machine;
+use std::fmt::Write;
+
+// Matches opcodes against a four character pattern such as "D01n" or "Fx0A". Hex digits must match;
+// any other character is a wildcard.
+pub struct OpcodePattern {
+    pub text: String,
+    mask: u16,
+    value: u16
+}
+
+impl OpcodePattern {
+    pub fn parse(text: &str) -> Result<OpcodePattern, String> {
+        if text.chars().count() != 4 {
+            return Err(format!("Opcode pattern '{}' must be four characters long", text));
+        }
+        let mut mask = 0u16;
+        let mut value = 0u16;
+        for c in text.chars() {
+            mask <<= 4;
+            value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                mask |= 0xF;
+                value |= digit as u16;
+            }
+        }
+        return Ok(OpcodePattern { text: text.to_string(), mask, value });
+    }
+
+    pub fn matches(&self, op: u16) -> bool {
+        return op & self.mask == self.value;
+    }
+}
+
+pub enum Command {
+    Step(u32),
+    Continue,
+    Break(u16),
+    BreakOpcode(OpcodePattern),
+    Watch(u16, u16),
+    WatchI,
+    Delete,
+    List,
+    Registers,
+    Memory(u16, u16),
+    Disassemble(Option<u16>),
+    Help,
+    Quit
+}
+
+pub const HELP: &str = "\
+Commands:
+    s, step [N]           Execute N instructions (default: 1)
+    c, continue           Resume execution
+    b, break <ADDR>       Break when PC reaches ADDR
+    bo, breakop <PATTERN> Break before an opcode matching PATTERN, e.g. D01n or Fx0A
+    w, watch <ADDR> [END] Break after memory from ADDR to END changes
+    wi, watchi            Break after I changes
+    l, list               List breakpoints and watchpoints
+    d, delete             Remove all breakpoints and watchpoints
+    r, regs               Show registers and the code around PC
+    x <ADDR> [LEN]        Dump LEN bytes of memory from ADDR (default: 16)
+    u, dis [ADDR]         Disassemble around ADDR (default: PC)
+    q, quit               Quit Rip8
+";
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let arg = |n: usize| words.get(n).copied();
+    return match words.first().copied().unwrap_or("step") {
+        "s" | "step" => Ok(Command::Step(match arg(1) {
+            Some(n) => n.parse::<u32>().map_err(|_| format!("Not a number: '{}'", n))?,
+            None => 1
+        })),
+        "c" | "continue" => Ok(Command::Continue),
+        "b" | "break" => Ok(Command::Break(parse_address(arg(1))?)),
+        "bo" | "breakop" => Ok(Command::BreakOpcode(OpcodePattern::parse(arg(1).ok_or("Missing opcode pattern")?)?)),
+        "w" | "watch" => {
+            let start = parse_address(arg(1))?;
+            let end = match arg(2) {
+                Some(_) => parse_address(arg(2))?,
+                None => start
+            };
+            if end < start {
+                return Err(String::from("Watch range ends before it starts"));
+            }
+            Ok(Command::Watch(start, end))
+        }
+        "wi" | "watchi" => Ok(Command::WatchI),
+        "l" | "list" => Ok(Command::List),
+        "d" | "delete" => Ok(Command::Delete),
+        "r" | "regs" => Ok(Command::Registers),
+        "x" => Ok(Command::Memory(parse_address(arg(1))?, match arg(2) {
+            Some(_) => parse_address(arg(2))?,
+            None => 16
+        })),
+        "u" | "dis" => Ok(Command::Disassemble(match arg(1) {
+            Some(_) => Some(parse_address(arg(1))?),
+            None => None
+        })),
+        "h" | "help" | "?" => Ok(Command::Help),
+        "q" | "quit" => Ok(Command::Quit),
+        other => Err(format!("Unknown command '{}', try 'help'", other))
+    };
+}
+
+// Addresses and lengths are hex, with or without a 0x prefix.
+fn parse_address(word: Option<&str>) -> Result<u16, String> {
+    let word = word.ok_or("Missing address")?;
+    let digits = word.trim_start_matches("0x").trim_start_matches("0X");
+    return u16::from_str_radix(digits, 16).map_err(|_| format!("Not a hex address: '{}'", word));
+}
+
+// What the watchpoints looked like before an instruction ran.
+pub struct WatchSnapshot {
+    memory: Vec<Vec<u8>>,
+    i: u16
+}
+
+pub struct Debugger {
+    pub paused: bool,
+    pub breakpoints: Vec<u16>,
+    pub opcode_breakpoints: Vec<OpcodePattern>,
+    pub memory_watchpoints: Vec<(u16, u16)>,
+    pub watch_i: bool,
+    // Set when resuming so the breakpoint that paused execution does not fire again straight away.
+    skip_breakpoints: bool
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        return Debugger {
+            paused: false,
+            breakpoints: Vec::new(),
+            opcode_breakpoints: Vec::new(),
+            memory_watchpoints: Vec::new(),
+            watch_i: false,
+            skip_breakpoints: false
+        };
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.skip_breakpoints = true;
+    }
+
+    // Called before the instruction at PC runs. Returns why execution should pause, if it should.
+    pub fn check_breakpoints(&mut self, machine: &Machine) -> Option<String> {
+        if self.skip_breakpoints {
+            self.skip_breakpoints = false;
+            return None;
+        }
+        if self.breakpoints.contains(&machine.pc) {
+            return Some(format!("Breakpoint at {:04X}", machine.pc));
+        }
+        let op = read_op(machine, machine.pc);
+        return self.opcode_breakpoints.iter()
+            .find(|pattern| pattern.matches(op))
+            .map(|pattern| format!("Opcode breakpoint {} at {:04X}", pattern.text, machine.pc));
+    }
+
+    pub fn watch(&self, machine: &Machine) -> WatchSnapshot {
+        return WatchSnapshot {
+            memory: self.memory_watchpoints.iter()
+                .map(|&(start, end)| machine.memory[start as usize..=end as usize].to_vec())
+                .collect(),
+            i: machine.i
+        };
+    }
+
+    // Called after an instruction runs. Returns why execution should pause, if it should.
+    pub fn check_watchpoints(&self, before: &WatchSnapshot, machine: &Machine) -> Option<String> {
+        if self.watch_i && machine.i != before.i {
+            return Some(format!("I changed from {:04X} to {:04X}", before.i, machine.i));
+        }
+        for (&(start, end), old) in self.memory_watchpoints.iter().zip(before.memory.iter()) {
+            let new = &machine.memory[start as usize..=end as usize];
+            if let Some(offset) = old.iter().zip(new.iter()).position(|(a, b)| a != b) {
+                return Some(format!(
+                    "Memory at {:04X} changed from {:02X} to {:02X}",
+                    start as usize + offset, old[offset], new[offset]
+                ));
+            }
+        }
+        return None;
+    }
+
+    pub fn list(&self) -> String {
+        let mut out = String::new();
+        for address in self.breakpoints.iter() {
+            writeln!(out, "break {:04X}", address).unwrap();
+        }
+        for pattern in self.opcode_breakpoints.iter() {
+            writeln!(out, "breakop {}", pattern.text).unwrap();
+        }
+        for (start, end) in self.memory_watchpoints.iter() {
+            writeln!(out, "watch {:04X}-{:04X}", start, end).unwrap();
+        }
+        if self.watch_i {
+            writeln!(out, "watch I").unwrap();
+        }
+        if out.is_empty() {
+            out.push_str("No breakpoints or watchpoints\n");
+        }
+        return out;
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.opcode_breakpoints.clear();
+        self.memory_watchpoints.clear();
+        self.watch_i = false;
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        return Debugger::new();
+    }
+}
+
+pub fn read_op(machine: &Machine, address: u16) -> u16 {
+    let address = address as usize;
+    return ((machine.memory[address] as u16) << 8) | (*machine.memory.get(address + 1).unwrap_or(&0) as u16);
+}
+
+// Lists `count` instructions either side of `address`, marking the one at PC.
+pub fn disassembly_around(machine: &Machine, address: u16, count: u16) -> String {
+    let mut out = String::new();
+    let span = count as usize * 2;
+    let end = (address as usize + span).min(machine.memory.len() - 2);
+    let mut cursor = (address as usize).saturating_sub(span);
+    while cursor <= end {
+        let address = cursor as u16;
+        let op = read_op(machine, address);
+        let marker = if address == machine.pc { "=>" } else { "  " };
+        writeln!(out, "{} {:04X}: {:04X}  {}", marker, address, op, disassemble(machine, address)).unwrap();
+        cursor += 2;
+    }
+    return out;
+}
+
+pub fn disassemble(machine: &Machine, address: u16) -> String {
+    let instruction = decode(read_op(machine, address));
+    return match instruction {
+        Instruction::LdILong => format!("LD I, 0x{:04X}", read_op(machine, address.wrapping_add(2))),
+        _ => instruction.to_string()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcode_patterns_match_wildcards() {
+        let pattern = OpcodePattern::parse("Dxyn").unwrap();
+        assert!(pattern.matches(0xD125));
+        assert!(!pattern.matches(0xC125));
+        let pattern = OpcodePattern::parse("F.0A").unwrap();
+        assert!(pattern.matches(0xF30A));
+        assert!(!pattern.matches(0xF30B));
+        assert!(OpcodePattern::parse("D12").is_err());
+    }
+
+    #[test]
+    fn disassembly_stops_at_the_end_of_memory() {
+        let machine = Machine::init();
+        let listing = disassembly_around(&machine, 0xFFFC, 4);
+        assert_eq!(listing.lines().count(), 6);
+        assert!(listing.lines().last().unwrap().contains("FFFE:"));
+        assert_eq!(disassembly_around(&machine, 0xFFFF, 0xFFFF).lines().count(), 0x8000);
+    }
+
+    #[test]
+    fn breakpoint_fires_once_per_visit() {
+        let mut machine = Machine::init();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(0x200);
+        machine.pc = 0x200;
+        assert!(debugger.check_breakpoints(&machine).is_some());
+        debugger.resume();
+        assert!(debugger.check_breakpoints(&machine).is_none());
+        assert!(debugger.check_breakpoints(&machine).is_some());
+    }
+
+    #[test]
+    fn watchpoints_report_changes() {
+        let mut machine = Machine::init();
+        let mut debugger = Debugger::new();
+        debugger.memory_watchpoints.push((0x300, 0x30F));
+        debugger.watch_i = true;
+        let before = debugger.watch(&machine);
+        machine.memory[0x305] = 7;
+        assert_eq!(
+            debugger.check_watchpoints(&before, &machine).unwrap(),
+            "Memory at 0305 changed from 00 to 07"
+        );
+        let before = debugger.watch(&machine);
+        machine.i = 0x123;
+        assert!(debugger.check_watchpoints(&before, &machine).unwrap().starts_with("I changed"));
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse_command("").unwrap(), Command::Step(1)));
+        assert!(matches!(parse_command("s 10").unwrap(), Command::Step(10)));
+        assert!(matches!(parse_command("b 0x2A0").unwrap(), Command::Break(0x2A0)));
+        assert!(matches!(parse_command("w 300 30f").unwrap(), Command::Watch(0x300, 0x30F)));
+        assert!(parse_command("w 30f 300").is_err());
+        assert!(parse_command("frobnicate").is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::instruction::{decode, Instruction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Data,
+    Jump,
+    Subroutine
+}
+
+// The result of walking a ROM from its entry point. Addresses are absolute, not offsets into the ROM.
+pub struct Analysis {
+    pub origin: u16,
+    pub instructions: BTreeSet<u16>,
+    pub labels: BTreeMap<u16, LabelKind>
+}
+
+impl Analysis {
+    pub fn label_name(&self, address: u16) -> Option<String> {
+        return self.labels.get(&address).map(|kind| {
+            let prefix = match kind {
+                LabelKind::Data => "data",
+                LabelKind::Jump => "L",
+                LabelKind::Subroutine => "sub"
+            };
+            format!("{}{:03X}", prefix, address)
+        });
+    }
+}
+
+// Follows every path the program can take from `origin`. Anything never reached as an instruction is
+// treated as data. Computed jumps (Bnnn) and SYS calls end a path since their targets are unknown.
+pub fn analyze(rom: &[u8], origin: u16) -> Analysis {
+    let end = origin as usize + rom.len();
+    let word_at = |address: usize| -> Option<u16> {
+        if address < origin as usize || address + 1 >= end {
+            return None;
+        }
+        let offset = address - origin as usize;
+        return Some(((rom[offset] as u16) << 8) | (rom[offset + 1] as u16));
+    };
+    let in_rom = |address: u16| (address as usize) >= origin as usize && (address as usize) < end;
+
+    let mut instructions = BTreeSet::new();
+    let mut labels = BTreeMap::new();
+    let mut pending = vec![origin];
+    while let Some(address) = pending.pop() {
+        if instructions.contains(&address) {
+            continue;
+        }
+        let op = match word_at(address as usize) {
+            Some(op) => op,
+            None => continue
+        };
+        let instruction = decode(op);
+        if let Instruction::Unknown { .. } = instruction {
+            continue;
+        }
+        instructions.insert(address);
+        // None past the end of the address space, which ends the path.
+        let next = address.checked_add(instruction.size());
+        match instruction {
+            Instruction::Jp { nnn } => {
+                add_label(&mut labels, nnn, LabelKind::Jump);
+                pending.push(nnn);
+            }
+            Instruction::Call { nnn } => {
+                add_label(&mut labels, nnn, LabelKind::Subroutine);
+                pending.push(nnn);
+                pending.extend(next);
+            }
+            Instruction::SeByte { .. } | Instruction::SneByte { .. } | Instruction::SeReg { .. }
+            | Instruction::SneReg { .. } | Instruction::Skp { .. } | Instruction::Sknp { .. } => {
+                // Both the next instruction and the one after it are reachable.
+                if let Some(next) = next {
+                    let skipped_size = match word_at(next as usize) {
+                        Some(0xF000u16) => 4u16,
+                        _ => 2u16
+                    };
+                    pending.push(next);
+                    pending.extend(next.checked_add(skipped_size));
+                }
+            }
+            Instruction::LdI { nnn } => {
+                if in_rom(nnn) {
+                    add_label(&mut labels, nnn, LabelKind::Data);
+                }
+                pending.extend(next);
+            }
+            Instruction::LdILong => {
+                if let Some(long) = word_at(address as usize + 2) {
+                    if in_rom(long) {
+                        add_label(&mut labels, long, LabelKind::Data);
+                    }
+                }
+                pending.extend(next);
+            }
+            Instruction::Ret | Instruction::Exit | Instruction::JpV0 { .. } | Instruction::Sys { .. } => {}
+            _ => pending.extend(next)
+        }
+    }
+    return Analysis { origin, instructions, labels };
+}
+
+// Code labels win over data labels when an address is used as both.
+fn add_label(labels: &mut BTreeMap<u16, LabelKind>, address: u16, kind: LabelKind) {
+    let entry = labels.entry(address).or_insert(kind);
+    if kind > *entry {
+        *entry = kind;
+    }
+}
+
+// Annotated listing: one instruction per line, with data bytes shown as sprite rows.
+pub fn listing(rom: &[u8], origin: u16) -> String {
+    let analysis = analyze(rom, origin);
+    let mut out = String::new();
+    let end = origin as usize + rom.len();
+    let mut address = origin as usize;
+    while address < end {
+        let offset = address - origin as usize;
+        if let Some(name) = analysis.label_name(address as u16) {
+            writeln!(out, "{}:", name).unwrap();
+        }
+        if analysis.instructions.contains(&(address as u16)) {
+            let op = ((rom[offset] as u16) << 8) | (rom[offset + 1] as u16);
+            let instruction = decode(op);
+            if let Instruction::LdILong = instruction {
+                let long = rom.get(offset + 2..offset + 4)
+                    .map(|bytes| ((bytes[0] as u16) << 8) | (bytes[1] as u16))
+                    .unwrap_or(0u16);
+                let operand = analysis.label_name(long).unwrap_or_else(|| format!("0x{:04X}", long));
+                writeln!(out, "    {:04X}: {:04X} {:04X}  LD I, {}", address, op, long, operand).unwrap();
+            } else {
+                writeln!(out, "    {:04X}: {:04X}       {}", address, op, format_instruction(&analysis, instruction)).unwrap();
+            }
+            // Code that jumps into the middle of an instruction gets a line of its own from there on.
+            let next = address + instruction.size() as usize;
+            let overlapped = (address + 1..next.min(end))
+                .find(|&inside| analysis.instructions.contains(&(inside as u16)) || analysis.labels.contains_key(&(inside as u16)));
+            if let Some(inside) = overlapped {
+                writeln!(out, "    ; {:04X} overlaps the instruction above", inside).unwrap();
+                address = inside;
+            } else {
+                address = next;
+            }
+        } else {
+            let byte = rom[offset];
+            let sprite: String = (0..8).map(|bit| if byte & (0x80u8 >> bit) != 0 { '#' } else { '.' }).collect();
+            writeln!(out, "    {:04X}: {:02X}         DB 0x{:02X}  ; {}", address, byte, byte, sprite).unwrap();
+            address += 1;
+        }
+    }
+    return out;
+}
+
+// Replaces addresses with label names where the walk found one.
+fn format_instruction(analysis: &Analysis, instruction: Instruction) -> String {
+    let target = |nnn: u16| analysis.label_name(nnn).unwrap_or_else(|| format!("0x{:03X}", nnn));
+    return match instruction {
+        Instruction::Jp { nnn } => format!("JP {}", target(nnn)),
+        Instruction::Call { nnn } => format!("CALL {}", target(nnn)),
+        Instruction::JpV0 { nnn } => format!("JP V0, {}", target(nnn)),
+        Instruction::LdI { nnn } => format!("LD I, {}", target(nnn)),
+        _ => instruction.to_string()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(words: &[u16]) -> Vec<u8> {
+        return words.iter().flat_map(|word| vec![(word >> 8) as u8, *word as u8]).collect();
+    }
+
+    #[test]
+    fn walk_separates_code_from_data() {
+        // CALL 0x208, JP 0x202 (loop), data byte, RET at 0x208.
+        let program = rom(&[0x2208, 0x1202, 0xF0F0, 0x0000, 0xA204, 0x00EE]);
+        let analysis = analyze(&program, 0x200);
+        assert!(analysis.instructions.contains(&0x200));
+        assert!(analysis.instructions.contains(&0x202));
+        assert!(!analysis.instructions.contains(&0x204));
+        assert!(analysis.instructions.contains(&0x208));
+        assert!(analysis.instructions.contains(&0x20A));
+        assert_eq!(analysis.labels.get(&0x208), Some(&LabelKind::Subroutine));
+        assert_eq!(analysis.labels.get(&0x202), Some(&LabelKind::Jump));
+        assert_eq!(analysis.labels.get(&0x204), Some(&LabelKind::Data));
+    }
+
+    #[test]
+    fn skips_reach_both_paths() {
+        // SE V0, 0 over a long load, then EXIT.
+        let program = rom(&[0x3000, 0xF000, 0x0208, 0x00FD, 0xAAAA]);
+        let analysis = analyze(&program, 0x200);
+        assert!(analysis.instructions.contains(&0x202));
+        assert!(analysis.instructions.contains(&0x206));
+        assert!(!analysis.instructions.contains(&0x208));
+        assert_eq!(analysis.labels.get(&0x208), Some(&LabelKind::Data));
+    }
+
+    #[test]
+    fn code_running_to_the_end_of_memory_stops_there() {
+        let program = rom(&[0x6000u16; 0x8000]);
+        let analysis = analyze(&program, 0x0000);
+        assert_eq!(analysis.instructions.len(), 0x8000);
+        assert!(analysis.instructions.contains(&0xFFFE));
+        let skip = rom(&[0x3000u16; 0x8000]);
+        assert!(analyze(&skip, 0x0000).instructions.contains(&0xFFFE));
+    }
+
+    #[test]
+    fn listing_uses_labels() {
+        let program = rom(&[0xA204, 0x1200, 0x3C00]);
+        let text = listing(&program, 0x200);
+        assert!(text.contains("L200:\n"));
+        assert!(text.contains("LD I, data204"));
+        assert!(text.contains("JP L200"));
+        assert!(text.contains("DB 0x3C  ; ..####.."));
+    }
+
+    #[test]
+    fn listing_shows_code_inside_other_instructions() {
+        // JP 0x201, which runs the second byte of the jump and the next one as SYS 0x112.
+        let text = listing(&[0x12u8, 0x01u8, 0x12u8], 0x200);
+        assert!(text.contains("JP L201\n"));
+        assert!(text.contains("; 0201 overlaps the instruction above\nL201:\n    0201: 0112"));
+    }
+}
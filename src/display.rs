@@ -0,0 +1,114 @@
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+// The frame buffer. Each pixel holds one bit per XO-CHIP bitplane, so plain CHIP-8 only ever sets bit 0.
+// Pixels are stored row-major for the active resolution, so the first width * height bytes can be
+// uploaded as they are; switching resolution clears it.
+pub struct Display {
+    pub hires: bool,
+    pub pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT]
+}
+
+impl Display {
+    pub fn new() -> Display {
+        return Display {
+            hires: false,
+            pixels: [0u8; HIRES_WIDTH * HIRES_HEIGHT]
+        };
+    }
+
+    pub fn width(&self) -> usize {
+        return if self.hires { HIRES_WIDTH } else { LORES_WIDTH };
+    }
+
+    pub fn height(&self) -> usize {
+        return if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT };
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        return self.pixels[y * self.width() + x];
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, pixel: u8) {
+        let width = self.width();
+        self.pixels[y * width + x] = pixel;
+    }
+
+    // The active part of the frame buffer, one row after another.
+    pub fn rows(&self) -> &[u8] {
+        return &self.pixels[..self.width() * self.height()];
+    }
+
+    pub fn is_blank(&self) -> bool {
+        return self.pixels.iter().all(|&p| p == 0);
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels = [0u8; HIRES_WIDTH * HIRES_HEIGHT];
+    }
+
+    pub fn clear_planes(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.clear();
+    }
+
+    pub fn scroll_down(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for x in 0..width {
+            for y in (0..height).rev() {
+                let source = if y >= n { self.get(x, y - n) } else { 0u8 };
+                self.move_planes(x, y, source, planes);
+            }
+        }
+    }
+
+    pub fn scroll_up(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for x in 0..width {
+            for y in 0..height {
+                let source = if y + n < height { self.get(x, y + n) } else { 0u8 };
+                self.move_planes(x, y, source, planes);
+            }
+        }
+    }
+
+    pub fn scroll_right(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for x in (0..width).rev() {
+            for y in 0..height {
+                let source = if x >= n { self.get(x - n, y) } else { 0u8 };
+                self.move_planes(x, y, source, planes);
+            }
+        }
+    }
+
+    pub fn scroll_left(&mut self, n: usize, planes: u8) {
+        let (width, height) = (self.width(), self.height());
+        for x in 0..width {
+            for y in 0..height {
+                let source = if x + n < width { self.get(x + n, y) } else { 0u8 };
+                self.move_planes(x, y, source, planes);
+            }
+        }
+    }
+
+    // Replaces the selected planes of (x, y) with those of source, leaving the other planes alone.
+    fn move_planes(&mut self, x: usize, y: usize, source: u8, planes: u8) {
+        let pixel = (self.get(x, y) & !planes) | (source & planes);
+        self.set(x, y, pixel);
+    }
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        return Display::new();
+    }
+}
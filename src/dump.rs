@@ -0,0 +1,70 @@
+use crate::machine::Machine;
+use std::fmt::Write;
+
+// One character per combination of the two bitplanes.
+const PIXEL_CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+pub fn display_ascii(machine: &Machine) -> String {
+    let display = machine.display.lock().unwrap();
+    let mut out = String::new();
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            out.push(PIXEL_CHARS[display.get(x, y) as usize]);
+        }
+        out.push('\n');
+    }
+    return out;
+}
+
+// FNV-1a over the visible pixels, for comparing runs without storing whole screens.
+pub fn display_hash(machine: &Machine) -> u64 {
+    let display = machine.display.lock().unwrap();
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    for y in 0..display.height() {
+        for x in 0..display.width() {
+            hash ^= display.get(x, y) as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01B3u64);
+        }
+    }
+    return hash;
+}
+
+pub fn registers(machine: &Machine) -> String {
+    let mut out = String::new();
+    for (i, v) in machine.v.iter().enumerate() {
+        write!(out, "V{:X}={:02X}{}", i, v, if i % 8 == 7 { '\n' } else { ' ' }).unwrap();
+    }
+    writeln!(
+        out,
+        "I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+        machine.i, machine.pc, machine.sp, *machine.dt.lock().unwrap(), *machine.st.lock().unwrap()
+    ).unwrap();
+    write!(out, "Stack:").unwrap();
+    for entry in machine.stack.iter() {
+        write!(out, " {:03X}", entry).unwrap();
+    }
+    out.push('\n');
+    return out;
+}
+
+// Hex dump in rows of 16 bytes. Runs of all-zero rows are collapsed into a single '*'.
+pub fn memory_hex(machine: &Machine) -> String {
+    let mut out = String::new();
+    let mut skipping = false;
+    for (row, bytes) in machine.memory.chunks(16).enumerate() {
+        if bytes.iter().all(|&b| b == 0) {
+            if !skipping {
+                out.push_str("*\n");
+                skipping = true;
+            }
+            continue;
+        }
+        skipping = false;
+        write!(out, "{:04X}:", row * 16).unwrap();
+        for b in bytes {
+            write!(out, " {:02X}", b).unwrap();
+        }
+        out.push('\n');
+    }
+    return out;
+}
@@ -1,266 +1,349 @@
-use crate::machine::Machine;
+use crate::machine::{KeyWait, Machine};
 use rand::{Rng};
-use std::sync::mpsc::{Receiver};
-use crate::KeyEvent;
-use crate::draw_event::DrawEvent;
-use sdl2::event::EventSender;
+use crate::hooks::Hooks;
+use crate::key_event::KeyEvent;
+use crate::quirks::LoadStoreIncrement;
+use crate::instruction::{decode, Instruction};
+use crate::fault::{CpuFault, FaultKind, StepOutcome};
 
-pub fn execute(machine: &mut Machine, key_receiver: &Receiver<KeyEvent>, event_sender: &EventSender) {
-    while let Some(key_event) = key_receiver.try_recv().ok() {
+// Runs one instruction. Faults are detected before the instruction changes anything.
+pub fn execute(machine: &mut Machine, hooks: &mut dyn Hooks) -> Result<StepOutcome, CpuFault> {
+    while let Some(key_event) = hooks.poll_key() {
         process_key_event(machine, &key_event);
     }
 
-    let op = ((machine.memory[machine.pc as usize] as u16) << 8) | (machine.memory[(machine.pc + 1u16) as usize] as u16);
-    machine.pc += 2u16;
+    let pc = machine.pc;
+    if pc as usize + 2 > machine.quirks.memory_size {
+        return Err(CpuFault { kind: FaultKind::PcOutOfRange, pc, op: 0u16 });
+    }
+    let op = read_word(machine, pc);
+    machine.pc = machine.pc.saturating_add(2u16);
+    let fault = |machine: &mut Machine, kind: FaultKind| {
+        machine.pc = pc;
+        return Err(CpuFault { kind, pc, op });
+    };
 
-    match op & 0xF000 {
-        0x0000u16 => {
-            match op & 0x00FFu16 {
-                // 00E0 - CLS
-                0x00E0u16 => {
-                    let mut updated = false;
-                    let mut display = machine.display.lock().unwrap();
-                    for i in 0..display.len() {
-                        updated = updated || display[i];
-                        display[i] = false
-                    }
-                    if updated {
-                        // draw_sender.send(DrawEvent {});
-                        event_sender.push_custom_event(DrawEvent {}).unwrap();
-                    }
-                }
+    match decode(op) {
+        // 00Cn - SCD nibble
+        Instruction::ScrollDown { n } => {
+            // Scroll display n lines down.
+            machine.display.lock().unwrap().scroll_down(n as usize, machine.planes);
+            hooks.display_updated();
+        }
 
-                // 00EE - RET
-                0x00EEu16 => {
-                    machine.pc = machine.stack[machine.sp as usize];
-                    machine.sp -= 1u16;
-                }
+        // 00Dn - SCU nibble
+        Instruction::ScrollUp { n } => {
+            // Scroll display n lines up.
+            machine.display.lock().unwrap().scroll_up(n as usize, machine.planes);
+            hooks.display_updated();
+        }
 
-                _ => panic!("Unknown opcode: {:x}", op)
+        // 00E0 - CLS
+        Instruction::Cls => {
+            let mut display = machine.display.lock().unwrap();
+            let updated = !display.is_blank();
+            display.clear_planes(machine.planes);
+            if updated {
+                hooks.display_updated();
             }
         }
 
+        // 00EE - RET
+        Instruction::Ret => {
+            if machine.sp == 0u16 {
+                return fault(machine, FaultKind::StackUnderflow);
+            }
+            machine.sp -= 1u16;
+            machine.pc = machine.stack[machine.sp as usize];
+        }
+
+        // 00FB - SCR
+        Instruction::ScrollRight => {
+            // Scroll display 4 pixels right.
+            machine.display.lock().unwrap().scroll_right(4, machine.planes);
+            hooks.display_updated();
+        }
+
+        // 00FC - SCL
+        Instruction::ScrollLeft => {
+            // Scroll display 4 pixels left.
+            machine.display.lock().unwrap().scroll_left(4, machine.planes);
+            hooks.display_updated();
+        }
+
+        // 00FD - EXIT
+        Instruction::Exit => {
+            // Exit the interpreter.
+            machine.exited = true;
+            return Ok(StepOutcome::Exited);
+        }
+
+        // 00FE - LOW
+        Instruction::Low => {
+            // Disable high resolution graphics mode.
+            machine.display.lock().unwrap().set_hires(false);
+            hooks.display_updated();
+        }
+
+        // 00FF - HIGH
+        Instruction::High => {
+            // Enable 128x64 high resolution graphics mode.
+            machine.display.lock().unwrap().set_hires(true);
+            hooks.display_updated();
+        }
+
         // 1nnn - JP addr
-        0x1000u16 => {
+        Instruction::Jp { nnn } => {
             // Jump to nnn.
-            machine.pc = op & 0x0FFFu16
+            machine.pc = nnn
         }
 
         // 2nnn - CALL addr
-        0x2000u16 => {
-            // Call nnn.
-            machine.sp += 1u16;
+        Instruction::Call { nnn } => {
+            // Call nnn. sp counts the return addresses on the stack, so all 16 slots can be used.
+            if machine.sp as usize == machine.stack.len() {
+                return fault(machine, FaultKind::StackOverflow);
+            }
             machine.stack[machine.sp as usize] = machine.pc;
-            machine.pc = op & 0x0FFFu16
+            machine.sp += 1u16;
+            machine.pc = nnn
         }
 
         // 3xkk - SE Vx, byte
-        0x3000u16 => {
+        Instruction::SeByte { x, kk } => {
             // Skip next instruction if Vx = kk.
-            let x = get_x(op);
-            let kk = get_kk(op);
             if machine.v[x] == kk {
-                machine.pc += 2u16;
+                skip_next_instruction(machine);
             }
         }
 
         // 4xkk - SNE Vx, byte
-        0x4000u16 => {
+        Instruction::SneByte { x, kk } => {
             // Skip next instruction if Vx != kk.
-            let x = get_x(op);
-            let kk = get_kk(op);
             if machine.v[x] != kk {
-                machine.pc += 2u16
+                skip_next_instruction(machine);
             }
         }
 
-        0x5000u16 => {
-            match op & 0x000Fu16 {
-                // 5xy0 - SE Vx, Vy
-                0x0000u16 => {
-                    // Skip next instruction if Vx = Vy.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    if machine.v[x] == machine.v[y] {
-                        machine.pc += 2u16
-                    }
-                }
+        // 5xy0 - SE Vx, Vy
+        Instruction::SeReg { x, y } => {
+            // Skip next instruction if Vx = Vy.
+            if machine.v[x] == machine.v[y] {
+                skip_next_instruction(machine);
+            }
+        }
+
+        // 5xy2 - LD [I], Vx-Vy
+        Instruction::SaveRange { x, y } => {
+            // Store registers Vx through Vy in memory starting at location I.
+            if let Err(kind) = check_memory(machine, x.max(y) - x.min(y) + 1) {
+                return fault(machine, kind);
+            }
+            for (offset, register) in register_range(x, y).enumerate() {
+                machine.memory[machine.i as usize + offset] = machine.v[register];
+            }
+        }
 
-                _ => panic!("Unknown opcode: {:x}", op)
+        // 5xy3 - LD Vx-Vy, [I]
+        Instruction::LoadRange { x, y } => {
+            // Read registers Vx through Vy from memory starting at location I.
+            if let Err(kind) = check_memory(machine, x.max(y) - x.min(y) + 1) {
+                return fault(machine, kind);
+            }
+            for (offset, register) in register_range(x, y).enumerate() {
+                machine.v[register] = machine.memory[machine.i as usize + offset];
             }
         }
 
         // 6xkk - LD Vx, byte
-        0x6000u16 => {
+        Instruction::LdByte { x, kk } => {
             // Set Vx = kk.
-            let x = get_x(op);
-            let kk = get_kk(op);
             machine.v[x] = kk
         }
 
         // 7xkk - ADD Vx, byte
-        0x7000u16 => {
+        Instruction::AddByte { x, kk } => {
             // Set Vx = Vx + kk.
-            let x = get_x(op);
-            let kk = get_kk(op);
             machine.v[x] = machine.v[x].wrapping_add(kk);
         }
 
-        0x8000u16 => {
-            match op & 0x000Fu16 {
-                // 8xy0 - LD Vx, Vy
-                0x0000u16 => {
-                    // Set Vx = Vy.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[x] = machine.v[y]
-                }
-
-                // 8xy1 - OR Vx, Vy
-                0x0001u16 => {
-                    // Set Vx = Vx OR Vy.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[x] = machine.v[x] | machine.v[y];
-                }
+        // 8xy0 - LD Vx, Vy
+        Instruction::LdReg { x, y } => {
+            // Set Vx = Vy.
+            machine.v[x] = machine.v[y]
+        }
 
-                // 8xy2 - AND Vx, Vy
-                0x0002u16 => {
-                    // Set Vx = Vx AND Vy.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[x] = machine.v[x] | machine.v[y];
-                }
+        // 8xy1 - OR Vx, Vy
+        Instruction::Or { x, y } => {
+            // Set Vx = Vx OR Vy.
+            machine.v[x] |= machine.v[y];
+            if machine.quirks.logic_resets_vf {
+                machine.v[0xF] = 0u8;
+            }
+        }
 
-                // 8xy3 - XOR Vx, Vy
-                0x0003u16 => {
-                    // Set Vx = Vx XOR Vy.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[x] = machine.v[x] ^ machine.v[y];
-                }
+        // 8xy2 - AND Vx, Vy
+        Instruction::And { x, y } => {
+            // Set Vx = Vx AND Vy.
+            machine.v[x] &= machine.v[y];
+            if machine.quirks.logic_resets_vf {
+                machine.v[0xF] = 0u8;
+            }
+        }
 
-                // 8xy4 - ADD Vx, Vy
-                0x0004u16 => {
-                    // Set Vx = Vx + Vy, set VF = carry.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    let result = (machine.v[x] as u16) + (machine.v[y] as u16);
-                    machine.v[x] = result as u8;
-                    machine.v[0xF] = (result >> 8) as u8;
-                }
+        // 8xy3 - XOR Vx, Vy
+        Instruction::Xor { x, y } => {
+            // Set Vx = Vx XOR Vy.
+            machine.v[x] ^= machine.v[y];
+            if machine.quirks.logic_resets_vf {
+                machine.v[0xF] = 0u8;
+            }
+        }
 
-                // 8xy5 - SUB Vx, Vy
-                0x0005u16 => {
-                    // Set Vx = Vx - Vy, set VF = NOT borrow.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[0xF] = if machine.v[x] > machine.v[y] {
-                        1u8
-                    } else {
-                        0u8
-                    };
-                    machine.v[x] = machine.v[x].wrapping_sub(machine.v[y]);
-                }
+        // 8xy4 - ADD Vx, Vy
+        Instruction::AddReg { x, y } => {
+            // Set Vx = Vx + Vy, set VF = carry.
+            let result = (machine.v[x] as u16) + (machine.v[y] as u16);
+            machine.v[x] = result as u8;
+            machine.v[0xF] = (result >> 8) as u8;
+        }
 
-                // 8xy6 - SHR Vx {, Vy}
-                0x0006u16 => {
-                    // Set Vx = Vx SHR 1.
-                    let x = get_x(op);
-                    machine.v[0xF] = if (machine.v[x] & 0b1u8) == 0b1u8 {
-                        1u8
-                    } else {
-                        0u8
-                    };
-                    machine.v[x] >>= 1;
-                }
+        // 8xy5 - SUB Vx, Vy
+        Instruction::Sub { x, y } => {
+            // Set Vx = Vx - Vy, set VF = NOT borrow.
+            let not_borrow = if machine.v[x] >= machine.v[y] {
+                1u8
+            } else {
+                0u8
+            };
+            machine.v[x] = machine.v[x].wrapping_sub(machine.v[y]);
+            machine.v[0xF] = not_borrow;
+        }
 
-                // 8xy7 - SUBN Vx, Vy
-                0x0007u16 => {
-                    // Set Vx = Vy - Vx, set VF = NOT borrow.
-                    let x = get_x(op);
-                    let y = get_y(op);
-                    machine.v[0xF] = if machine.v[y] > machine.v[x] {
-                        1u8
-                    } else {
-                        0u8
-                    };
-                    machine.v[x] = machine.v[y].wrapping_sub(machine.v[x])
-                }
+        // 8xy6 - SHR Vx {, Vy}
+        Instruction::Shr { x, y } => {
+            // Set Vx = Vx SHR 1.
+            if machine.quirks.shift_uses_vy {
+                machine.v[x] = machine.v[y];
+            }
+            let shifted_out = machine.v[x] & 0b1u8;
+            machine.v[x] >>= 1;
+            machine.v[0xF] = shifted_out;
+        }
 
-                // 8xyE - SHL Vx {, Vy}
-                0x000Eu16 => {
-                    // Set Vx = Vx SHL 1.
-                    let x = get_x(op);
-                    machine.v[0xF] = if (machine.v[x] & 0b1000_0000u8) == 0b1000_0000u8 {
-                        1u8
-                    } else {
-                        0u8
-                    };
-                    machine.v[x] <<= 1
-                }
+        // 8xy7 - SUBN Vx, Vy
+        Instruction::Subn { x, y } => {
+            // Set Vx = Vy - Vx, set VF = NOT borrow.
+            let not_borrow = if machine.v[y] >= machine.v[x] {
+                1u8
+            } else {
+                0u8
+            };
+            machine.v[x] = machine.v[y].wrapping_sub(machine.v[x]);
+            machine.v[0xF] = not_borrow;
+        }
 
-                _ => panic!("Unknown opcode: {:x}", op)
+        // 8xyE - SHL Vx {, Vy}
+        Instruction::Shl { x, y } => {
+            // Set Vx = Vx SHL 1.
+            if machine.quirks.shift_uses_vy {
+                machine.v[x] = machine.v[y];
             }
+            let shifted_out = machine.v[x] >> 7;
+            machine.v[x] <<= 1;
+            machine.v[0xF] = shifted_out;
         }
 
         // 9xy0 - SNE Vx, Vy
-        0x9000u16 => {
+        Instruction::SneReg { x, y } => {
             // Skip next instruction if Vx != Vy.
-            let x = get_x(op);
-            let y = get_y(op);
             if machine.v[x] != machine.v[y] {
-                machine.pc += 2u16
+                skip_next_instruction(machine);
             }
         }
 
         // Annn - LD I, addr
-        0xA000u16 => {
+        Instruction::LdI { nnn } => {
             // Set I = nnn.
-            machine.i = op & 0x0FFFu16
+            machine.i = nnn
         }
 
         // Bnnn - JP V0, addr
-        0xB000u16 => {
+        Instruction::JpV0 { nnn } => {
             // Jump to location nnn + V0.
-            machine.pc = (op & 0x0FFFu16) + (machine.v[0] as u16)
+            let offset = if machine.quirks.jump_uses_vx {
+                machine.v[(nnn >> 8) as usize]
+            } else {
+                machine.v[0]
+            };
+            machine.pc = nnn + (offset as u16)
         }
 
         // Cxkk - RND Vx, byte
-        0xC000u16 => {
+        Instruction::Rnd { x, kk } => {
             // Set Vx = random byte AND kk.
-            let x = get_x(op);
-            let kk = get_kk(op);
-            let rb = rand::thread_rng().gen_range(0u8..=255u8);
+            let rb = machine.rng.gen_range(0u8..=255u8);
             machine.v[x] = rb & kk
         }
 
         // Dxyn - DRW Vx, Vy, nibble
-        0xD000u16 => {
+        Instruction::Drw { x, y, n } => {
             // Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
-            let x = get_x(op);
-            let y = get_y(op);
-            let n = get_n(op) as u16;
-            let vx = machine.v[x] as u16;
-            let vy = machine.v[y] as u16;
+            // Dxy0 draws a 16x16 sprite from 32 bytes instead.
+            if machine.quirks.display_wait {
+                let mut vblank = machine.vblank.lock().unwrap();
+                if !*vblank {
+                    // Retry until the frontend signals the next frame.
+                    machine.pc = pc;
+                    return Ok(StepOutcome::Blocked);
+                }
+                *vblank = false;
+            }
+            let n = n as usize;
+            let (sprite_width, sprite_height) = if n == 0 { (16usize, 16usize) } else { (8usize, n) };
+            let sprite_size = sprite_width / 8 * sprite_height;
+            if let Err(kind) = check_memory(machine, sprite_size * machine.planes.count_ones() as usize) {
+                return fault(machine, kind);
+            }
+            let mut display = machine.display.lock().unwrap();
+            let width = display.width();
+            let height = display.height();
+            let vx = machine.v[x] as usize % width;
+            let vy = machine.v[y] as usize % height;
             let mut display_updated = false;
             let mut collision = false;
-            let mut display = machine.display.lock().unwrap();
-            for y_offset in 0u16..n {
-                let sprite_byte = machine.memory[machine.i as usize + y_offset as usize];
-                for x_offset in 0u16..8u16 {
-                    let update = ((sprite_byte >> (7 - x_offset)) & 0b1u8) == 0b1u8;
-                    let display_position = ((((vx + x_offset) % 64u16) * 32u16) + ((vy + y_offset) % 32u16)) as usize;
-                    let existing_pixel = display[display_position];
-                    let display_pixel = existing_pixel ^ update;
-                    display[display_position] = display_pixel;
-                    display_updated |= existing_pixel != display_pixel;
-                    collision |= existing_pixel && !display_pixel
+            // Each selected bitplane is drawn in turn from consecutive sprites in memory.
+            let mut sprite_address = machine.i as usize;
+            for plane in [0b01u8, 0b10u8].iter().copied().filter(|plane| machine.planes & plane != 0) {
+                for y_offset in 0..sprite_height {
+                    if machine.quirks.clip_sprites && vy + y_offset >= height {
+                        break;
+                    }
+                    let row_address = sprite_address + y_offset * sprite_width / 8;
+                    let sprite_row = if sprite_width == 16 {
+                        ((machine.memory[row_address] as u16) << 8) | (machine.memory[row_address + 1] as u16)
+                    } else {
+                        (machine.memory[row_address] as u16) << 8
+                    };
+                    for x_offset in 0..sprite_width {
+                        if machine.quirks.clip_sprites && vx + x_offset >= width {
+                            break;
+                        }
+                        if ((sprite_row >> (15 - x_offset)) & 0b1u16) == 0u16 {
+                            continue;
+                        }
+                        let display_x = (vx + x_offset) % width;
+                        let display_y = (vy + y_offset) % height;
+                        let existing_pixel = display.get(display_x, display_y);
+                        display.set(display_x, display_y, existing_pixel ^ plane);
+                        display_updated = true;
+                        collision |= existing_pixel & plane != 0u8
+                    }
                 }
+                sprite_address += sprite_size;
             }
             if display_updated {
-                // draw_sender.send(DrawEvent {});
-                event_sender.push_custom_event(DrawEvent {}).unwrap();
+                hooks.display_updated();
             }
             machine.v[0xF] = if collision {
                 1u8
@@ -269,140 +352,918 @@ pub fn execute(machine: &mut Machine, key_receiver: &Receiver<KeyEvent>, event_s
             };
         }
 
-        0xE000u16 => {
-            match op & 0x00FFu16 {
-                // Ex9E - SKP Vx
-                0x009Eu16 => {
-                    // Skip next instruction if key with the value of Vx is pressed.
-                    let x = get_x(op);
-                    if machine.keys[machine.v[x] as usize] {
-                        machine.pc += 2u16
-                    }
-                }
+        // Ex9E - SKP Vx
+        Instruction::Skp { x } => {
+            // Skip next instruction if key with the value of Vx is pressed.
+            if machine.keys[(machine.v[x] & 0x0Fu8) as usize] {
+                skip_next_instruction(machine);
+            }
+        }
 
-                // ExA1 - SKNP Vx
-                0x00A1u16 => {
-                    // Skip next instruction if key with the value of Vx is not pressed.
-                    let x = get_x(op);
-                    if !machine.keys[machine.v[x] as usize] {
-                        machine.pc += 2u16;
-                    }
-                }
+        // ExA1 - SKNP Vx
+        Instruction::Sknp { x } => {
+            // Skip next instruction if key with the value of Vx is not pressed.
+            if !machine.keys[(machine.v[x] & 0x0Fu8) as usize] {
+                skip_next_instruction(machine);
+            }
+        }
 
-                _ => panic!("Unknown opcode: {:x}", op)
+        // F000 nnnn - LD I, long addr
+        Instruction::LdILong => {
+            // Set I = the 16-bit address in the following word.
+            if machine.pc as usize + 2 > machine.quirks.memory_size {
+                return fault(machine, FaultKind::PcOutOfRange);
             }
+            machine.i = read_word(machine, machine.pc);
+            machine.pc += 2u16;
         }
 
-        0xF000u16 => {
-            match op & 0x00FFu16 {
-                // Fx07 - LD Vx, DT
-                0x0007u16 => {
-                    // Set Vx = delay timer value.
-                    let x = get_x(op);
-                    machine.v[x] = *machine.dt.lock().unwrap();
-                }
+        // Fn01 - PLANE n
+        Instruction::Plane { n } => {
+            // Select the bitplanes drawn to by CLS, DRW and the scroll instructions.
+            machine.planes = n & 0b11u8;
+        }
 
-                // Fx0A - LD Vx, K
-                0x000Au16 => {
-                    // Wait for a key press, store the value of the key in Vx.
-                    let x = get_x(op);
-                    for i in 0..machine.keys.len() {
-                        if machine.keys[i] {
-                            machine.v[x] = i as u8;
-                            break;
-                        }
-                    }
-                    while let Some(key_event) = key_receiver.recv().ok() {
-                        process_key_event(machine, &key_event);
-                        if key_event.pressed {
-                            machine.v[x] = key_event.key;
-                            break;
-                        }
-                    }
-                }
+        // F002 - AUDIO
+        Instruction::Audio => {
+            // Load the 16-byte audio pattern buffer from memory starting at location I.
+            if let Err(kind) = check_memory(machine, 16) {
+                return fault(machine, kind);
+            }
+            let i = machine.i as usize;
+            let mut pattern = [0u8; 16];
+            pattern.copy_from_slice(&machine.memory[i..i + 16]);
+            machine.sound.lock().unwrap().pattern = Some(pattern);
+        }
 
-                // Fx15 - LD DT, Vx
-                0x0015u16 => {
-                    // Set delay timer = Vx.
-                    let x = get_x(op);
-                    *machine.dt.lock().unwrap() = machine.v[x];
-                }
+        // Fx07 - LD Vx, DT
+        Instruction::LdVxDt { x } => {
+            // Set Vx = delay timer value.
+            machine.v[x] = *machine.dt.lock().unwrap();
+        }
 
-                // Fx18 - LD ST, Vx
-                0x0018u16 => {
-                    // Set sound timer = Vx.
-                    let x = get_x(op);
-                    *machine.st.lock().unwrap() = machine.v[x];
+        // Fx0A - LD Vx, K
+        Instruction::LdVxK { x } => {
+            // Wait for a key press (or release, with the quirk), store the value of the key in Vx.
+            // Until then the instruction repeats, so timers and the frontend keep running.
+            match machine.key_wait {
+                KeyWait::Done(key) => {
+                    machine.v[x] = key;
+                    machine.key_wait = KeyWait::Idle;
                 }
-
-                // Fx1E - ADD I, Vx
-                0x001Eu16 => {
-                    // Set I = I + Vx.
-                    let x = get_x(op);
-                    machine.i += machine.v[x] as u16;
+                _ => {
+                    machine.key_wait = KeyWait::Waiting;
+                    machine.pc = pc;
+                    return Ok(StepOutcome::Blocked);
                 }
+            }
+        }
 
-                // Fx29 - LD F, Vx
-                0x0029u16 => {
-                    // Set I = location of sprite for digit Vx.
-                    let x = get_x(op);
-                    machine.i = machine.sprite_digits[machine.v[x] as usize];
-                }
+        // Fx15 - LD DT, Vx
+        Instruction::LdDtVx { x } => {
+            // Set delay timer = Vx.
+            *machine.dt.lock().unwrap() = machine.v[x];
+        }
 
-                // Fx33 - LD B, Vx
-                0x0033u16 => {
-                    // Store BCD representation of Vx in memory locations I, I+1, and I+2.
-                    let x = get_x(op);
-                    let hundreds = (machine.v[x] / 100u8) % 10u8;
-                    let tens = (machine.v[x] / 10u8) % 10u8;
-                    let ones = (machine.v[x]) % 10u8;
-                    machine.memory[machine.i as usize] = hundreds;
-                    machine.memory[machine.i as usize + 1usize] = tens;
-                    machine.memory[machine.i as usize + 2usize] = ones;
-                }
+        // Fx18 - LD ST, Vx
+        Instruction::LdStVx { x } => {
+            // Set sound timer = Vx.
+            *machine.st.lock().unwrap() = machine.v[x];
+        }
 
-                // Fx55 - LD [I], Vx
-                0x0055u16 => {
-                    // Store registers V0 through Vx in memory starting at location I.
-                    let x = get_x(op);
-                    for i in 0usize..=x {
-                        machine.memory[machine.i as usize + i] = machine.v[i]
-                    }
-                }
+        // Fx1E - ADD I, Vx
+        Instruction::AddIVx { x } => {
+            // Set I = I + Vx.
+            machine.i = machine.i.wrapping_add(machine.v[x] as u16);
+        }
 
-                // Fx65 - LD Vx, [I]
-                0x0065u16 => {
-                    // Read registers V0 through Vx from memory starting at location I.
-                    let x = get_x(op);
-                    for i in 0usize..=x {
-                        machine.v[i] = machine.memory[machine.i as usize + i]
-                    }
-                }
+        // Fx29 - LD F, Vx
+        Instruction::LdFVx { x } => {
+            // Set I = location of sprite for digit Vx.
+            machine.i = machine.sprite_digits[(machine.v[x] & 0x0Fu8) as usize];
+        }
 
-                _ => panic!("Unknown opcode: {:x}", op)
+        // Fx30 - LD HF, Vx
+        Instruction::LdHfVx { x } => {
+            // Set I = location of 8x10 sprite for digit Vx.
+            machine.i = machine.big_sprite_digits[(machine.v[x] & 0x0Fu8) as usize];
+        }
+
+        // Fx33 - LD B, Vx
+        Instruction::LdBVx { x } => {
+            // Store BCD representation of Vx in memory locations I, I+1, and I+2.
+            if let Err(kind) = check_memory(machine, 3) {
+                return fault(machine, kind);
             }
+            let hundreds = (machine.v[x] / 100u8) % 10u8;
+            let tens = (machine.v[x] / 10u8) % 10u8;
+            let ones = (machine.v[x]) % 10u8;
+            machine.memory[machine.i as usize] = hundreds;
+            machine.memory[machine.i as usize + 1usize] = tens;
+            machine.memory[machine.i as usize + 2usize] = ones;
         }
 
-        _ => panic!("Unknown opcode: {:x}", op)
+        // Fx3A - PITCH Vx
+        Instruction::Pitch { x } => {
+            // Set the audio pattern playback pitch = Vx.
+            machine.sound.lock().unwrap().pitch = machine.v[x];
+        }
+
+        // Fx55 - LD [I], Vx
+        Instruction::LdIVx { x } => {
+            // Store registers V0 through Vx in memory starting at location I.
+            if let Err(kind) = check_memory(machine, x + 1) {
+                return fault(machine, kind);
+            }
+            for i in 0usize..=x {
+                machine.memory[machine.i as usize + i] = machine.v[i]
+            }
+            increment_i_after_load_store(machine, x);
+        }
+
+        // Fx65 - LD Vx, [I]
+        Instruction::LdVxI { x } => {
+            // Read registers V0 through Vx from memory starting at location I.
+            if let Err(kind) = check_memory(machine, x + 1) {
+                return fault(machine, kind);
+            }
+            for i in 0usize..=x {
+                machine.v[i] = machine.memory[machine.i as usize + i]
+            }
+            increment_i_after_load_store(machine, x);
+        }
+
+        // Fx75 - LD R, Vx
+        Instruction::LdRVx { x } => {
+            // Store V0 through Vx in the RPL user flags.
+            machine.rpl[0..=x].copy_from_slice(&machine.v[0..=x]);
+        }
+
+        // Fx85 - LD Vx, R
+        Instruction::LdVxR { x } => {
+            // Read V0 through Vx from the RPL user flags.
+            machine.v[0..=x].copy_from_slice(&machine.rpl[0..=x]);
+        }
+
+        // 0nnn - SYS addr
+        Instruction::Sys { nnn } => return fault(machine, FaultKind::MachineCodeCall { address: nnn }),
+
+        Instruction::Unknown { .. } => return fault(machine, FaultKind::UnknownOpcode)
     }
+    return Ok(StepOutcome::Executed);
 }
 
-fn get_x(op: u16) -> usize {
-    return ((op & 0x0F00u16) >> 8) as usize;
+fn read_word(machine: &Machine, address: u16) -> u16 {
+    return ((machine.memory[address as usize] as u16) << 8) | (machine.memory[address as usize + 1] as u16);
 }
 
-fn get_y(op: u16) -> usize {
-    return ((op & 0x00F0u16) >> 4) as usize;
+// Skips the next instruction, which is four bytes long if it is F000 nnnn. A skip past the end of
+// memory faults when the next instruction is fetched.
+fn skip_next_instruction(machine: &mut Machine) {
+    let long = machine.memory.get(machine.pc as usize..machine.pc as usize + 2) == Some(&[0xF0u8, 0x00u8][..]);
+    machine.pc = machine.pc.saturating_add(if long { 4u16 } else { 2u16 });
 }
 
-fn get_n(op: u16) -> u8 {
-    return (op & 0x000Fu16) as u8;
+// Checks that `length` bytes starting at I are inside memory.
+fn check_memory(machine: &Machine, length: usize) -> Result<(), FaultKind> {
+    let address = machine.i as usize;
+    if address + length > machine.quirks.memory_size {
+        return Err(FaultKind::MemoryOutOfRange { address, length });
+    }
+    return Ok(());
 }
 
-fn get_kk(op: u16) -> u8 {
-    return op as u8;
+// Registers x through y, counting down if x > y.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    return if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    };
+}
+
+fn increment_i_after_load_store(machine: &mut Machine, x: usize) {
+    match machine.quirks.load_store_increment {
+        LoadStoreIncrement::None => {}
+        LoadStoreIncrement::X => machine.i = machine.i.wrapping_add(x as u16),
+        LoadStoreIncrement::XPlusOne => machine.i = machine.i.wrapping_add(x as u16 + 1u16)
+    }
 }
 
 fn process_key_event(machine: &mut Machine, key_event: &KeyEvent) {
-    machine.keys[key_event.key as usize] = key_event.pressed;
+    let key = key_event.key as usize;
+    if machine.key_wait == KeyWait::Waiting {
+        let finished = if machine.quirks.key_wait_release {
+            machine.keys[key] && !key_event.pressed
+        } else {
+            !machine.keys[key] && key_event.pressed
+        };
+        if finished {
+            machine.key_wait = KeyWait::Done(key_event.key);
+        }
+    }
+    machine.keys[key] = key_event.pressed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct TestHooks {
+        keys: VecDeque<KeyEvent>,
+        draws: usize
+    }
+
+    impl Hooks for TestHooks {
+        fn poll_key(&mut self) -> Option<KeyEvent> {
+            return self.keys.pop_front();
+        }
+
+        fn display_updated(&mut self) {
+            self.draws += 1;
+        }
+    }
+
+    fn load(program: &[u16]) -> Machine {
+        let mut machine = Machine::init();
+        for (i, op) in program.iter().enumerate() {
+            machine.memory[0x200 + i * 2] = (op >> 8) as u8;
+            machine.memory[0x200 + i * 2 + 1] = *op as u8;
+        }
+        machine.pc = 0x200;
+        return machine;
+    }
+
+    fn run(machine: &mut Machine, steps: usize) -> TestHooks {
+        let mut hooks = TestHooks { keys: VecDeque::new(), draws: 0 };
+        for _ in 0..steps {
+            execute(machine, &mut hooks).unwrap();
+        }
+        return hooks;
+    }
+
+    fn pixel(machine: &Machine, x: usize, y: usize) -> bool {
+        return machine.display.lock().unwrap().get(x, y) != 0;
+    }
+
+    #[test]
+    fn cls_clears_display() {
+        let mut machine = load(&[0x00E0]);
+        machine.display.lock().unwrap().set(5, 3, 1);
+        let hooks = run(&mut machine, 1);
+        assert!(machine.display.lock().unwrap().is_blank());
+        assert_eq!(hooks.draws, 1);
+    }
+
+    #[test]
+    fn cls_on_blank_display_does_not_redraw() {
+        let mut machine = load(&[0x00E0]);
+        assert_eq!(run(&mut machine, 1).draws, 0);
+    }
+
+    #[test]
+    fn call_and_ret() {
+        let mut machine = load(&[0x2206, 0x0000, 0x0000, 0x00EE]);
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x206);
+        assert_eq!(machine.sp, 1);
+        assert_eq!(machine.stack[0], 0x202);
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.sp, 0);
+    }
+
+    #[test]
+    fn jp() {
+        let mut machine = load(&[0x1ABC]);
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0xABC);
+    }
+
+    #[test]
+    fn se_byte() {
+        let mut machine = load(&[0x3342, 0x3342]);
+        machine.v[3] = 0x42;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x204);
+        machine.v[3] = 0x41;
+        machine.pc = 0x200;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+    }
+
+    #[test]
+    fn sne_byte() {
+        let mut machine = load(&[0x4342]);
+        machine.v[3] = 0x41;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x204);
+        machine.v[3] = 0x42;
+        machine.pc = 0x200;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+    }
+
+    #[test]
+    fn se_register() {
+        let mut machine = load(&[0x5120]);
+        machine.v[1] = 7;
+        machine.v[2] = 7;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x204);
+        machine.v[2] = 8;
+        machine.pc = 0x200;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+    }
+
+    #[test]
+    fn ld_byte() {
+        let mut machine = load(&[0x6A5C]);
+        run(&mut machine, 1);
+        assert_eq!(machine.v[0xA], 0x5C);
+    }
+
+    #[test]
+    fn add_byte_wraps_without_carry() {
+        let mut machine = load(&[0x7A02]);
+        machine.v[0xA] = 0xFF;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[0xA], 0x01);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn ld_register() {
+        let mut machine = load(&[0x8120]);
+        machine.v[2] = 0x33;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0x33);
+    }
+
+    #[test]
+    fn or() {
+        let mut machine = load(&[0x8121]);
+        machine.v[1] = 0b1100;
+        machine.v[2] = 0b1010;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b1110);
+    }
+
+    #[test]
+    fn and() {
+        let mut machine = load(&[0x8122]);
+        machine.v[1] = 0b1100;
+        machine.v[2] = 0b1010;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b1000);
+    }
+
+    #[test]
+    fn xor() {
+        let mut machine = load(&[0x8123]);
+        machine.v[1] = 0b1100;
+        machine.v[2] = 0b1010;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b0110);
+    }
+
+    #[test]
+    fn add_register_sets_carry() {
+        let mut machine = load(&[0x8124, 0x8124]);
+        machine.v[1] = 0xF0;
+        machine.v[2] = 0x20;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0x10);
+        assert_eq!(machine.v[0xF], 1);
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0x30);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn sub_sets_not_borrow() {
+        let mut machine = load(&[0x8125, 0x8125]);
+        machine.v[1] = 0x30;
+        machine.v[2] = 0x10;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0x20);
+        assert_eq!(machine.v[0xF], 1);
+        machine.v[1] = 0x00;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0xF0);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn sub_equal_registers_does_not_borrow() {
+        let mut machine = load(&[0x8125, 0x8117]);
+        machine.v[1] = 0x42;
+        machine.v[2] = 0x42;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0);
+        assert_eq!(machine.v[0xF], 1);
+        machine.v[0xF] = 0;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0);
+        assert_eq!(machine.v[0xF], 1);
+    }
+
+    #[test]
+    fn shr() {
+        let mut machine = load(&[0x8106, 0x8106]);
+        machine.v[1] = 0b101;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b10);
+        assert_eq!(machine.v[0xF], 1);
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b1);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn subn_sets_not_borrow() {
+        let mut machine = load(&[0x8127, 0x8127]);
+        machine.v[1] = 0x10;
+        machine.v[2] = 0x30;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0x20);
+        assert_eq!(machine.v[0xF], 1);
+        machine.v[1] = 0x40;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0xF0);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn shl() {
+        let mut machine = load(&[0x810E, 0x810E]);
+        machine.v[1] = 0b1100_0000;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b1000_0000);
+        assert_eq!(machine.v[0xF], 1);
+        machine.v[1] = 0b0100_0000;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b1000_0000);
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn flag_result_wins_when_vf_is_the_destination() {
+        let mut machine = load(&[0x8F15, 0x8F06, 0x8F0E]);
+        machine.v[0xF] = 0x05;
+        machine.v[1] = 0x01;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[0xF], 1);
+        machine.v[0xF] = 0x02;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[0xF], 0);
+        machine.v[0xF] = 0x80;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[0xF], 1);
+    }
+
+    #[test]
+    fn sne_register() {
+        let mut machine = load(&[0x9120]);
+        machine.v[1] = 7;
+        machine.v[2] = 8;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x204);
+        machine.v[2] = 7;
+        machine.pc = 0x200;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+    }
+
+    #[test]
+    fn ld_i() {
+        let mut machine = load(&[0xA123]);
+        run(&mut machine, 1);
+        assert_eq!(machine.i, 0x123);
+    }
+
+    #[test]
+    fn jp_v0() {
+        let mut machine = load(&[0xB300]);
+        machine.v[0] = 0x12;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x312);
+    }
+
+    #[test]
+    fn rnd_is_masked_and_seeded() {
+        let mut first = load(&[0xC10F, 0xC10F, 0xC10F, 0xC10F]);
+        let mut second = load(&[0xC10F, 0xC10F, 0xC10F, 0xC10F]);
+        first.seed_rng(1);
+        second.seed_rng(1);
+        for _ in 0..4 {
+            run(&mut first, 1);
+            run(&mut second, 1);
+            assert_eq!(first.v[1] & 0xF0, 0);
+            assert_eq!(first.v[1], second.v[1]);
+        }
+    }
+
+    #[test]
+    fn drw_xors_sprite_and_reports_collision() {
+        let mut machine = load(&[0xD122, 0xD122]);
+        machine.memory[0x300] = 0b1000_0001;
+        machine.memory[0x301] = 0b0100_0000;
+        machine.i = 0x300;
+        machine.v[1] = 2;
+        machine.v[2] = 3;
+        let hooks = run(&mut machine, 1);
+        assert!(pixel(&machine, 2, 3));
+        assert!(pixel(&machine, 9, 3));
+        assert!(pixel(&machine, 3, 4));
+        assert!(!pixel(&machine, 2, 4));
+        assert_eq!(machine.v[0xF], 0);
+        assert_eq!(hooks.draws, 1);
+        run(&mut machine, 1);
+        assert!(machine.display.lock().unwrap().is_blank());
+        assert_eq!(machine.v[0xF], 1);
+    }
+
+    #[test]
+    fn drw_wraps_around_the_screen() {
+        let mut machine = load(&[0xD121]);
+        machine.memory[0x300] = 0b1100_0000;
+        machine.i = 0x300;
+        machine.v[1] = 63;
+        machine.v[2] = 32;
+        run(&mut machine, 1);
+        assert!(pixel(&machine, 63, 0));
+        assert!(pixel(&machine, 0, 0));
+    }
+
+    #[test]
+    fn skp_and_sknp() {
+        let mut machine = load(&[0xE59E, 0x0000, 0xE5A1]);
+        machine.v[5] = 0xB;
+        machine.keys[0xB] = true;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x204);
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x206);
+        machine.keys[0xB] = false;
+        machine.pc = 0x200;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x202);
+        machine.pc = 0x204;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x208);
+    }
+
+    #[test]
+    fn ld_dt_and_st() {
+        let mut machine = load(&[0xF315, 0xF418, 0xF507]);
+        machine.v[3] = 9;
+        machine.v[4] = 4;
+        run(&mut machine, 3);
+        assert_eq!(*machine.dt.lock().unwrap(), 9);
+        assert_eq!(*machine.st.lock().unwrap(), 4);
+        assert_eq!(machine.v[5], 9);
+    }
+
+    #[test]
+    fn ld_key_waits_for_press() {
+        let mut machine = load(&[0xF20A]);
+        machine.keys[0x5] = true;
+        let mut hooks = TestHooks { keys: VecDeque::new(), draws: 0 };
+        // A key already held when the wait starts does not count.
+        assert_eq!(execute(&mut machine, &mut hooks), Ok(StepOutcome::Blocked));
+        assert_eq!(machine.pc, 0x200);
+        hooks.keys.push_back(KeyEvent { key: 0x3, pressed: false });
+        hooks.keys.push_back(KeyEvent { key: 0x7, pressed: true });
+        assert_eq!(execute(&mut machine, &mut hooks), Ok(StepOutcome::Executed));
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.v[2], 0x7);
+        assert!(machine.keys[0x7]);
+        assert_eq!(machine.key_wait, KeyWait::Idle);
+    }
+
+    #[test]
+    fn ld_key_waits_for_release_with_quirk() {
+        let mut machine = load(&[0xF20A]);
+        machine.quirks.key_wait_release = true;
+        let mut hooks = TestHooks { keys: VecDeque::new(), draws: 0 };
+        assert_eq!(execute(&mut machine, &mut hooks), Ok(StepOutcome::Blocked));
+        hooks.keys.push_back(KeyEvent { key: 0x9, pressed: true });
+        assert_eq!(execute(&mut machine, &mut hooks), Ok(StepOutcome::Blocked));
+        assert_eq!(machine.pc, 0x200);
+        hooks.keys.push_back(KeyEvent { key: 0x9, pressed: false });
+        assert_eq!(execute(&mut machine, &mut hooks), Ok(StepOutcome::Executed));
+        assert_eq!(machine.v[2], 0x9);
+        assert!(!machine.keys[0x9]);
+    }
+
+    #[test]
+    fn add_i() {
+        let mut machine = load(&[0xF31E]);
+        machine.i = 0x100;
+        machine.v[3] = 0x20;
+        run(&mut machine, 1);
+        assert_eq!(machine.i, 0x120);
+    }
+
+    #[test]
+    fn ld_f_points_at_font_digit() {
+        let mut machine = load(&[0xF329]);
+        machine.v[3] = 0xA;
+        run(&mut machine, 1);
+        assert_eq!(machine.i, machine.sprite_digits[0xA]);
+        assert_eq!(machine.memory[machine.i as usize], 0xF0);
+    }
+
+    #[test]
+    fn ld_b_stores_bcd() {
+        let mut machine = load(&[0xF333]);
+        machine.v[3] = 254;
+        machine.i = 0x300;
+        run(&mut machine, 1);
+        assert_eq!(&machine.memory[0x300..0x303], &[2, 5, 4]);
+    }
+
+    #[test]
+    fn ld_registers_round_trip_through_memory() {
+        let mut machine = load(&[0xF255, 0xF265]);
+        machine.v[0] = 1;
+        machine.v[1] = 2;
+        machine.v[2] = 3;
+        machine.v[3] = 4;
+        machine.i = 0x300;
+        run(&mut machine, 1);
+        assert_eq!(&machine.memory[0x300..0x304], &[1, 2, 3, 0]);
+        machine.v = [0u8; 16];
+        run(&mut machine, 1);
+        assert_eq!(&machine.v[0..4], &[1, 2, 3, 0]);
+        assert_eq!(machine.i, 0x300);
+    }
+
+    #[test]
+    fn quirk_shift_uses_vy() {
+        let mut machine = load(&[0x8126, 0x812E]);
+        machine.quirks.shift_uses_vy = true;
+        machine.v[2] = 0b1000_0011;
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b0100_0001);
+        assert_eq!(machine.v[0xF], 1);
+        run(&mut machine, 1);
+        assert_eq!(machine.v[1], 0b0000_0110);
+        assert_eq!(machine.v[0xF], 1);
+    }
+
+    #[test]
+    fn quirk_load_store_increment() {
+        let mut machine = load(&[0xF255, 0xF265]);
+        machine.i = 0x300;
+        machine.quirks.load_store_increment = LoadStoreIncrement::XPlusOne;
+        run(&mut machine, 1);
+        assert_eq!(machine.i, 0x303);
+        machine.quirks.load_store_increment = LoadStoreIncrement::X;
+        run(&mut machine, 1);
+        assert_eq!(machine.i, 0x305);
+    }
+
+    #[test]
+    fn quirk_jump_uses_vx() {
+        let mut machine = load(&[0xB310]);
+        machine.quirks.jump_uses_vx = true;
+        machine.v[0] = 0x01;
+        machine.v[3] = 0x04;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x314);
+    }
+
+    #[test]
+    fn quirk_logic_resets_vf() {
+        let mut machine = load(&[0x8121, 0x8122, 0x8123]);
+        machine.quirks.logic_resets_vf = true;
+        for _ in 0..3 {
+            machine.v[0xF] = 1;
+            run(&mut machine, 1);
+            assert_eq!(machine.v[0xF], 0);
+        }
+    }
+
+    #[test]
+    fn quirk_clip_sprites() {
+        let mut machine = load(&[0xD122]);
+        machine.quirks.clip_sprites = true;
+        machine.memory[0x300] = 0b1100_0000;
+        machine.memory[0x301] = 0b1100_0000;
+        machine.i = 0x300;
+        machine.v[1] = 63;
+        machine.v[2] = 31;
+        run(&mut machine, 1);
+        assert!(pixel(&machine, 63, 31));
+        assert!(!pixel(&machine, 0, 31));
+        assert!(!pixel(&machine, 63, 0));
+        assert!(!pixel(&machine, 0, 0));
+    }
+
+    #[test]
+    fn quirk_display_wait() {
+        let mut machine = load(&[0xD121, 0xD121]);
+        machine.quirks.display_wait = true;
+        machine.i = 0x300;
+        machine.memory[0x300] = 0x80;
+        run(&mut machine, 1);
+        assert_eq!(machine.pc, 0x200);
+        assert!(!pixel(&machine, 0, 0));
+        machine.tick_timers();
+        run(&mut machine, 2);
+        assert_eq!(machine.pc, 0x202);
+        assert!(pixel(&machine, 0, 0));
+    }
+
+    #[test]
+    fn high_and_low_switch_resolution() {
+        let mut machine = load(&[0x00FF, 0x00FE]);
+        run(&mut machine, 1);
+        assert_eq!(machine.display.lock().unwrap().width(), 128);
+        assert_eq!(machine.display.lock().unwrap().height(), 64);
+        run(&mut machine, 1);
+        assert_eq!(machine.display.lock().unwrap().width(), 64);
+    }
+
+    #[test]
+    fn scroll_down_right_and_left() {
+        let mut machine = load(&[0x00C3, 0x00FB, 0x00FC]);
+        machine.display.lock().unwrap().set(10, 2, 1);
+        run(&mut machine, 1);
+        assert!(pixel(&machine, 10, 5));
+        assert!(!pixel(&machine, 10, 2));
+        run(&mut machine, 1);
+        assert!(pixel(&machine, 14, 5));
+        run(&mut machine, 1);
+        assert!(pixel(&machine, 10, 5));
+        assert!(!pixel(&machine, 14, 5));
+    }
+
+    #[test]
+    fn drw_16x16_sprite_in_hires() {
+        let mut machine = load(&[0x00FF, 0xD120]);
+        for i in 0..32 {
+            machine.memory[0x300 + i] = if i % 2 == 0 { 0x80 } else { 0x01 };
+        }
+        machine.i = 0x300;
+        machine.v[1] = 100;
+        machine.v[2] = 40;
+        run(&mut machine, 2);
+        assert!(pixel(&machine, 100, 40));
+        assert!(pixel(&machine, 115, 55));
+        assert!(!pixel(&machine, 101, 40));
+        assert_eq!(machine.v[0xF], 0);
+    }
+
+    #[test]
+    fn ld_hf_points_at_big_font_digit() {
+        let mut machine = load(&[0xF330]);
+        machine.v[3] = 0x7;
+        run(&mut machine, 1);
+        assert_eq!(machine.i, machine.big_sprite_digits[0x7]);
+        assert_eq!(machine.memory[machine.i as usize + 4], 0x06);
+    }
+
+    #[test]
+    fn rpl_flags_round_trip() {
+        let mut machine = load(&[0xF275, 0xF285]);
+        machine.v[0] = 1;
+        machine.v[1] = 2;
+        machine.v[2] = 3;
+        run(&mut machine, 1);
+        assert_eq!(&machine.rpl[0..4], &[1, 2, 3, 0]);
+        machine.v = [0u8; 16];
+        run(&mut machine, 1);
+        assert_eq!(&machine.v[0..3], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn exit_stops_the_machine() {
+        let mut machine = load(&[0x00FD, 0x6105]);
+        let mut hooks = run(&mut machine, 0);
+        assert_eq!(machine.step(&mut hooks), Ok(StepOutcome::Exited));
+        assert_eq!(machine.step(&mut hooks), Ok(StepOutcome::Exited));
+        assert!(machine.exited);
+        assert_eq!(machine.v[1], 0);
+    }
+
+    #[test]
+    fn long_load_sets_i_and_is_skipped_whole() {
+        let mut machine = load(&[0xF000, 0xBEEF, 0x3000, 0xF000, 0x1234, 0x6101]);
+        run(&mut machine, 1);
+        assert_eq!(machine.i, 0xBEEF);
+        assert_eq!(machine.pc, 0x204);
+        run(&mut machine, 2);
+        assert_eq!(machine.v[1], 0x01);
+        assert_eq!(machine.i, 0xBEEF);
+    }
+
+    #[test]
+    fn save_and_load_register_ranges() {
+        let mut machine = load(&[0x5242, 0x5422, 0x5243]);
+        machine.v[2] = 0x22;
+        machine.v[3] = 0x33;
+        machine.v[4] = 0x44;
+        machine.i = 0x300;
+        run(&mut machine, 1);
+        assert_eq!(&machine.memory[0x300..0x303], &[0x22, 0x33, 0x44]);
+        run(&mut machine, 1);
+        assert_eq!(&machine.memory[0x300..0x302], &[0x44, 0x33]);
+        machine.v = [0u8; 16];
+        run(&mut machine, 1);
+        assert_eq!(&machine.v[2..5], &[0x44, 0x33, 0x22]);
+        assert_eq!(machine.i, 0x300);
+    }
+
+    #[test]
+    fn drw_uses_selected_planes() {
+        let mut machine = load(&[0xF201, 0xD011, 0xF301, 0xD011, 0xF101, 0x00E0]);
+        machine.memory[0x300] = 0x80;
+        machine.memory[0x301] = 0xC0;
+        machine.i = 0x300;
+        run(&mut machine, 2);
+        assert_eq!(machine.display.lock().unwrap().get(0, 0), 0b10);
+        run(&mut machine, 2);
+        assert_eq!(machine.display.lock().unwrap().get(0, 0), 0b01);
+        assert_eq!(machine.display.lock().unwrap().get(1, 0), 0b10);
+        assert_eq!(machine.v[0xF], 1);
+        run(&mut machine, 2);
+        assert_eq!(machine.display.lock().unwrap().get(0, 0), 0b00);
+        assert_eq!(machine.display.lock().unwrap().get(1, 0), 0b10);
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch() {
+        let mut machine = load(&[0xF002, 0xF53A]);
+        for i in 0..16 {
+            machine.memory[0x300 + i] = i as u8;
+        }
+        machine.i = 0x300;
+        machine.v[5] = 0x70;
+        run(&mut machine, 2);
+        let sound = machine.sound.lock().unwrap();
+        assert_eq!(sound.pattern.unwrap()[15], 15);
+        assert_eq!(sound.pitch, 0x70);
+    }
+
+    #[test]
+    fn unknown_opcodes_and_sys_fault() {
+        let mut machine = load(&[0x5121]);
+        let mut hooks = run(&mut machine, 0);
+        assert_eq!(
+            execute(&mut machine, &mut hooks),
+            Err(CpuFault { kind: FaultKind::UnknownOpcode, pc: 0x200, op: 0x5121 })
+        );
+        assert_eq!(machine.pc, 0x200);
+        let mut machine = load(&[0x0123]);
+        assert_eq!(
+            execute(&mut machine, &mut hooks).unwrap_err().kind,
+            FaultKind::MachineCodeCall { address: 0x123 }
+        );
+    }
+
+    #[test]
+    fn stack_faults() {
+        let mut machine = load(&[0x00EE]);
+        let mut hooks = run(&mut machine, 0);
+        assert_eq!(execute(&mut machine, &mut hooks).unwrap_err().kind, FaultKind::StackUnderflow);
+        let mut machine = load(&[0x2200]);
+        run(&mut machine, 16);
+        assert_eq!(execute(&mut machine, &mut hooks).unwrap_err().kind, FaultKind::StackOverflow);
+        assert_eq!(machine.sp, 16);
+    }
+
+    #[test]
+    fn memory_faults_leave_the_machine_unchanged() {
+        let mut machine = load(&[0xAFFE, 0xF033]);
+        run(&mut machine, 1);
+        let mut hooks = run(&mut machine, 0);
+        assert_eq!(
+            execute(&mut machine, &mut hooks).unwrap_err().kind,
+            FaultKind::MemoryOutOfRange { address: 0xFFE, length: 3 }
+        );
+        assert_eq!(machine.pc, 0x202);
+        assert_eq!(machine.memory[0xFFE], 0);
+
+        let mut machine = load(&[0xAFFC, 0xD015]);
+        run(&mut machine, 1);
+        assert!(execute(&mut machine, &mut hooks).is_err());
+        machine.quirks.memory_size = 0x10000;
+        assert!(execute(&mut machine, &mut hooks).is_ok());
+    }
+
+    #[test]
+    fn pc_past_the_end_faults() {
+        let mut machine = load(&[0x1FFE]);
+        let mut hooks = run(&mut machine, 1);
+        assert_eq!(machine.pc, 0xFFE);
+        assert_eq!(execute(&mut machine, &mut hooks).unwrap_err().kind, FaultKind::MachineCodeCall { address: 0 });
+        machine.memory[0xFFE] = 0x60;
+        run(&mut machine, 1);
+        assert_eq!(execute(&mut machine, &mut hooks).unwrap_err().kind, FaultKind::PcOutOfRange);
+    }
 }
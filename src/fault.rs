@@ -0,0 +1,57 @@
+use std::fmt;
+
+// What a successful step did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    Executed,
+    // The instruction is waiting (for a key or the next frame) and will run again on the next step.
+    Blocked,
+    // 00FD ran, or had already run.
+    Exited
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    UnknownOpcode,
+    // 0nnn calls machine code on the original hardware, which cannot be emulated.
+    MachineCodeCall { address: u16 },
+    StackOverflow,
+    StackUnderflow,
+    // An instruction would read or write `length` bytes at `address`, past the end of memory.
+    MemoryOutOfRange { address: usize, length: usize },
+    PcOutOfRange
+}
+
+// An instruction that cannot run. The machine is left as it was before the instruction, with PC
+// pointing at it, so the state can be inspected in the debugger.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuFault {
+    pub kind: FaultKind,
+    pub pc: u16,
+    pub op: u16
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultKind::UnknownOpcode => write!(f, "Unknown opcode"),
+            FaultKind::MachineCodeCall { address } => write!(f, "Call to machine code at 0x{:03X}", address),
+            FaultKind::StackOverflow => write!(f, "Stack overflow"),
+            FaultKind::StackUnderflow => write!(f, "Return with an empty stack"),
+            FaultKind::MemoryOutOfRange { address, length } => write!(
+                f,
+                "Access to {} bytes at 0x{:04X} runs past the end of memory",
+                length, address
+            ),
+            FaultKind::PcOutOfRange => write!(f, "PC ran past the end of memory")
+        }
+    }
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{} at PC={:04X} (opcode {:04X})", self.kind, self.pc, self.op);
+    }
+}
+
+impl std::error::Error for CpuFault {}
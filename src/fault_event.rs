@@ -0,0 +1,12 @@
+use rip_8::fault::CpuFault;
+
+// Sent by the CPU thread when the ROM faults. The thread then waits for a FaultAction.
+pub struct FaultEvent {
+    pub fault: CpuFault
+}
+
+pub enum FaultAction {
+    Reset,
+    Debug,
+    Quit
+}
@@ -0,0 +1,38 @@
+use std::sync::Mutex;
+use rip_8::display::{Display, HIRES_HEIGHT, HIRES_WIDTH};
+
+// The last complete frame, copied from the display by the CPU thread at the end of each frame so the
+// renderer never sees a half-drawn one.
+pub struct Frame {
+    pub hires: bool,
+    pub pixels: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    // The frame before, for blending. It moves on every emulated frame, including ones where nothing was
+    // drawn, so a sprite that stops moving leaves no ghost behind.
+    pub previous: [u8; HIRES_WIDTH * HIRES_HEIGHT],
+    // Emulated frames published so far, so the renderer can tell how many it missed.
+    pub count: u64,
+    // Set when a new frame is published and cleared when the renderer picks it up.
+    pub dirty: bool
+}
+
+impl Frame {
+    pub fn new() -> Frame {
+        return Frame {
+            hires: false,
+            pixels: [0u8; HIRES_WIDTH * HIRES_HEIGHT],
+            previous: [0u8; HIRES_WIDTH * HIRES_HEIGHT],
+            count: 0u64,
+            dirty: true
+        };
+    }
+
+    pub fn publish(&mut self, display: &Mutex<Display>) {
+        let display = display.lock().unwrap();
+        // A resolution change moves every pixel, so there is nothing to blend with.
+        self.previous = if display.hires == self.hires { self.pixels } else { [0u8; HIRES_WIDTH * HIRES_HEIGHT] };
+        self.hires = display.hires;
+        self.pixels = display.pixels;
+        self.count += 1;
+        self.dirty = true;
+    }
+}
@@ -0,0 +1,10 @@
+use crate::key_event::KeyEvent;
+
+// How a frontend feeds input to the machine and learns about display changes.
+pub trait Hooks {
+    // Next pending key event, without blocking.
+    fn poll_key(&mut self) -> Option<KeyEvent>;
+
+    // Called after an instruction changes the display.
+    fn display_updated(&mut self);
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+
+// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction. Register operands are indices into V.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 0nnn - SYS addr
+    Sys { nnn: u16 },
+    // 00Cn - SCD nibble
+    ScrollDown { n: u8 },
+    // 00Dn - SCU nibble
+    ScrollUp { n: u8 },
+    // 00E0 - CLS
+    Cls,
+    // 00EE - RET
+    Ret,
+    // 00FB - SCR
+    ScrollRight,
+    // 00FC - SCL
+    ScrollLeft,
+    // 00FD - EXIT
+    Exit,
+    // 00FE - LOW
+    Low,
+    // 00FF - HIGH
+    High,
+    // 1nnn - JP addr
+    Jp { nnn: u16 },
+    // 2nnn - CALL addr
+    Call { nnn: u16 },
+    // 3xkk - SE Vx, byte
+    SeByte { x: usize, kk: u8 },
+    // 4xkk - SNE Vx, byte
+    SneByte { x: usize, kk: u8 },
+    // 5xy0 - SE Vx, Vy
+    SeReg { x: usize, y: usize },
+    // 5xy2 - LD [I], Vx-Vy
+    SaveRange { x: usize, y: usize },
+    // 5xy3 - LD Vx-Vy, [I]
+    LoadRange { x: usize, y: usize },
+    // 6xkk - LD Vx, byte
+    LdByte { x: usize, kk: u8 },
+    // 7xkk - ADD Vx, byte
+    AddByte { x: usize, kk: u8 },
+    // 8xy0 - LD Vx, Vy
+    LdReg { x: usize, y: usize },
+    // 8xy1 - OR Vx, Vy
+    Or { x: usize, y: usize },
+    // 8xy2 - AND Vx, Vy
+    And { x: usize, y: usize },
+    // 8xy3 - XOR Vx, Vy
+    Xor { x: usize, y: usize },
+    // 8xy4 - ADD Vx, Vy
+    AddReg { x: usize, y: usize },
+    // 8xy5 - SUB Vx, Vy
+    Sub { x: usize, y: usize },
+    // 8xy6 - SHR Vx {, Vy}
+    Shr { x: usize, y: usize },
+    // 8xy7 - SUBN Vx, Vy
+    Subn { x: usize, y: usize },
+    // 8xyE - SHL Vx {, Vy}
+    Shl { x: usize, y: usize },
+    // 9xy0 - SNE Vx, Vy
+    SneReg { x: usize, y: usize },
+    // Annn - LD I, addr
+    LdI { nnn: u16 },
+    // Bnnn - JP V0, addr
+    JpV0 { nnn: u16 },
+    // Cxkk - RND Vx, byte
+    Rnd { x: usize, kk: u8 },
+    // Dxyn - DRW Vx, Vy, nibble
+    Drw { x: usize, y: usize, n: u8 },
+    // Ex9E - SKP Vx
+    Skp { x: usize },
+    // ExA1 - SKNP Vx
+    Sknp { x: usize },
+    // F000 nnnn - LD I, long addr. The address is the following word.
+    LdILong,
+    // Fn01 - PLANE n
+    Plane { n: u8 },
+    // F002 - AUDIO
+    Audio,
+    // Fx07 - LD Vx, DT
+    LdVxDt { x: usize },
+    // Fx0A - LD Vx, K
+    LdVxK { x: usize },
+    // Fx15 - LD DT, Vx
+    LdDtVx { x: usize },
+    // Fx18 - LD ST, Vx
+    LdStVx { x: usize },
+    // Fx1E - ADD I, Vx
+    AddIVx { x: usize },
+    // Fx29 - LD F, Vx
+    LdFVx { x: usize },
+    // Fx30 - LD HF, Vx
+    LdHfVx { x: usize },
+    // Fx33 - LD B, Vx
+    LdBVx { x: usize },
+    // Fx3A - PITCH Vx
+    Pitch { x: usize },
+    // Fx55 - LD [I], Vx
+    LdIVx { x: usize },
+    // Fx65 - LD Vx, [I]
+    LdVxI { x: usize },
+    // Fx75 - LD R, Vx
+    LdRVx { x: usize },
+    // Fx85 - LD Vx, R
+    LdVxR { x: usize },
+    Unknown { op: u16 }
+}
+
+pub fn decode(op: u16) -> Instruction {
+    let x = ((op & 0x0F00u16) >> 8) as usize;
+    let y = ((op & 0x00F0u16) >> 4) as usize;
+    let n = (op & 0x000Fu16) as u8;
+    let kk = op as u8;
+    let nnn = op & 0x0FFFu16;
+
+    return match op & 0xF000u16 {
+        0x0000u16 => match op {
+            0x00C0u16..=0x00CFu16 => Instruction::ScrollDown { n },
+            0x00D0u16..=0x00DFu16 => Instruction::ScrollUp { n },
+            0x00E0u16 => Instruction::Cls,
+            0x00EEu16 => Instruction::Ret,
+            0x00FBu16 => Instruction::ScrollRight,
+            0x00FCu16 => Instruction::ScrollLeft,
+            0x00FDu16 => Instruction::Exit,
+            0x00FEu16 => Instruction::Low,
+            0x00FFu16 => Instruction::High,
+            _ => Instruction::Sys { nnn }
+        },
+        0x1000u16 => Instruction::Jp { nnn },
+        0x2000u16 => Instruction::Call { nnn },
+        0x3000u16 => Instruction::SeByte { x, kk },
+        0x4000u16 => Instruction::SneByte { x, kk },
+        0x5000u16 => match n {
+            0x0 => Instruction::SeReg { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => Instruction::Unknown { op }
+        },
+        0x6000u16 => Instruction::LdByte { x, kk },
+        0x7000u16 => Instruction::AddByte { x, kk },
+        0x8000u16 => match n {
+            0x0 => Instruction::LdReg { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::Shr { x, y },
+            0x7 => Instruction::Subn { x, y },
+            0xE => Instruction::Shl { x, y },
+            _ => Instruction::Unknown { op }
+        },
+        0x9000u16 => match n {
+            0x0 => Instruction::SneReg { x, y },
+            _ => Instruction::Unknown { op }
+        },
+        0xA000u16 => Instruction::LdI { nnn },
+        0xB000u16 => Instruction::JpV0 { nnn },
+        0xC000u16 => Instruction::Rnd { x, kk },
+        0xD000u16 => Instruction::Drw { x, y, n },
+        0xE000u16 => match kk {
+            0x9E => Instruction::Skp { x },
+            0xA1 => Instruction::Sknp { x },
+            _ => Instruction::Unknown { op }
+        },
+        _ => match kk {
+            0x00 if x == 0 => Instruction::LdILong,
+            0x01 => Instruction::Plane { n: x as u8 },
+            0x02 if x == 0 => Instruction::Audio,
+            0x07 => Instruction::LdVxDt { x },
+            0x0A => Instruction::LdVxK { x },
+            0x15 => Instruction::LdDtVx { x },
+            0x18 => Instruction::LdStVx { x },
+            0x1E => Instruction::AddIVx { x },
+            0x29 => Instruction::LdFVx { x },
+            0x30 => Instruction::LdHfVx { x },
+            0x33 => Instruction::LdBVx { x },
+            0x3A => Instruction::Pitch { x },
+            0x55 => Instruction::LdIVx { x },
+            0x65 => Instruction::LdVxI { x },
+            0x75 => Instruction::LdRVx { x },
+            0x85 => Instruction::LdVxR { x },
+            _ => Instruction::Unknown { op }
+        }
+    };
+}
+
+impl Instruction {
+    // Size in bytes, including the address word that follows F000.
+    pub fn size(&self) -> u16 {
+        return match self {
+            Instruction::LdILong => 4u16,
+            _ => 2u16
+        };
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match *self {
+            Instruction::Sys { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::Low => write!(f, "LOW"),
+            Instruction::High => write!(f, "HIGH"),
+            Instruction::Jp { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SeByte { x, kk } => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SneByte { x, kk } => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            Instruction::SeReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LdByte { x, kk } => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            Instruction::AddByte { x, kk } => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Instruction::LdReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SneReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JpV0 { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Rnd { x, kk } => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Instruction::Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp { x } => write!(f, "SKP V{:X}", x),
+            Instruction::Sknp { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LdILong => write!(f, "LD I, long"),
+            Instruction::Plane { n } => write!(f, "PLANE {}", n),
+            Instruction::Audio => write!(f, "AUDIO"),
+            Instruction::LdVxDt { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::LdVxK { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::LdDtVx { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::LdStVx { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIVx { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LdFVx { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LdHfVx { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::LdBVx { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Pitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::LdIVx { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LdVxI { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LdRVx { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LdVxR { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::Unknown { op } => write!(f, "DW 0x{:04X}", op)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_operands() {
+        assert_eq!(decode(0xD12F), Instruction::Drw { x: 1, y: 2, n: 0xF });
+        assert_eq!(decode(0x7A05), Instruction::AddByte { x: 0xA, kk: 0x05 });
+        assert_eq!(decode(0xB3C0), Instruction::JpV0 { nnn: 0x3C0 });
+        assert_eq!(decode(0x00C4), Instruction::ScrollDown { n: 4 });
+        assert_eq!(decode(0xF201), Instruction::Plane { n: 2 });
+    }
+
+    #[test]
+    fn exact_matches_only() {
+        assert_eq!(decode(0x01E0), Instruction::Sys { nnn: 0x1E0 });
+        assert_eq!(decode(0x5121), Instruction::Unknown { op: 0x5121 });
+        assert_eq!(decode(0xF100), Instruction::Unknown { op: 0xF100 });
+        assert_eq!(decode(0xE19F), Instruction::Unknown { op: 0xE19F });
+    }
+
+    #[test]
+    fn formats_classic_mnemonics() {
+        assert_eq!(decode(0x6A02).to_string(), "LD VA, 0x02");
+        assert_eq!(decode(0xD015).to_string(), "DRW V0, V1, 5");
+        assert_eq!(decode(0xF065).to_string(), "LD V0, [I]");
+        assert_eq!(decode(0x1234).to_string(), "JP 0x234");
+        assert_eq!(decode(0xF000).size(), 4);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use sdl2::controller::Button;
+use sdl2::keyboard::Scancode;
+use rip_8::key_event::KeyEvent;
+use rip_8::keymap::KeyBindings;
+
+// Host keys, by position, and controller buttons to CHIP-8 keys.
+pub struct KeyMap {
+    keys: HashMap<Scancode, u8>,
+    buttons: HashMap<Button, u8>,
+    // The mapped keys and buttons held down, and how many of them hold each CHIP-8 key, so a key bound
+    // more than once is only released when the last of them is.
+    held_keys: HashSet<Scancode>,
+    // Buttons by the instance id of their controller, so unplugging one releases what it held.
+    held_buttons: HashSet<(u32, Button)>,
+    press_counts: [u8; 16]
+}
+
+impl KeyMap {
+    pub fn new(bindings: &KeyBindings) -> Result<KeyMap, String> {
+        let mut keys = HashMap::new();
+        for (key, names) in bindings.keys.iter().enumerate() {
+            for name in names.iter() {
+                let scancode = Scancode::from_name(name)
+                    .ok_or_else(|| format!("Unknown host key '{}' for CHIP-8 key {:X}", name, key))?;
+                keys.insert(scancode, key as u8);
+            }
+        }
+        let mut buttons = HashMap::new();
+        for (key, names) in bindings.buttons.iter().enumerate() {
+            for name in names.iter() {
+                let button = Button::from_string(name)
+                    .ok_or_else(|| format!("Unknown controller button '{}' for CHIP-8 key {:X}", name, key))?;
+                buttons.insert(button, key as u8);
+            }
+        }
+        return Ok(KeyMap {
+            keys,
+            buttons,
+            held_keys: HashSet::new(),
+            held_buttons: HashSet::new(),
+            press_counts: [0u8; 16]
+        });
+    }
+
+    pub fn handle_key_press(&mut self, key_sender: &Sender<KeyEvent>, scancode: Scancode, pressed: bool) {
+        if let Some(&key) = self.keys.get(&scancode) {
+            // A press or release the map did not see the other half of, e.g. across a focus change, is ignored.
+            let changed = if pressed { self.held_keys.insert(scancode) } else { self.held_keys.remove(&scancode) };
+            if changed {
+                self.update_key(key_sender, key, pressed);
+            }
+        }
+    }
+
+    pub fn handle_button_press(&mut self, key_sender: &Sender<KeyEvent>, controller: u32, button: Button, pressed: bool) {
+        if let Some(&key) = self.buttons.get(&button) {
+            let held = (controller, button);
+            let changed = if pressed { self.held_buttons.insert(held) } else { self.held_buttons.remove(&held) };
+            if changed {
+                self.update_key(key_sender, key, pressed);
+            }
+        }
+    }
+
+    // A controller that is unplugged sends no button releases, so send them for whatever it held.
+    pub fn release_controller(&mut self, key_sender: &Sender<KeyEvent>, controller: u32) {
+        let released: Vec<(u32, Button)> = self.held_buttons.iter().copied()
+            .filter(|&(instance, _)| instance == controller)
+            .collect();
+        for (instance, button) in released {
+            self.handle_button_press(key_sender, instance, button, false);
+        }
+    }
+
+    // Sends a press for the first input holding the key and a release for the last one letting go.
+    fn update_key(&mut self, key_sender: &Sender<KeyEvent>, key: u8, pressed: bool) {
+        let count = &mut self.press_counts[key as usize];
+        if pressed {
+            *count += 1;
+            if *count == 1u8 {
+                key_sender.send(KeyEvent { key, pressed }).unwrap();
+            }
+        } else {
+            *count -= 1;
+            if *count == 0u8 {
+                key_sender.send(KeyEvent { key, pressed }).unwrap();
+            }
+        }
+    }
+}
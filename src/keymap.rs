@@ -0,0 +1,333 @@
+use crate::config::config_dir;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The CHIP-8 keypad as it is laid out on the COSMAC VIP.
+pub const KEYPAD_ROWS: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF]
+];
+
+// Scancode names of the keys in the same place on the left of a keyboard. Scancodes are positions,
+// so this is 1234/QWER/ASDF/ZXCV on QWERTY and the same physical keys on AZERTY or Dvorak.
+const DEFAULT_KEYS: [[&str; 4]; 4] = [
+    ["1", "2", "3", "4"],
+    ["Q", "W", "E", "R"],
+    ["A", "S", "D", "F"],
+    ["Z", "X", "C", "V"]
+];
+
+// SDL game controller button names. The d-pad sits on the same keys as WASD above.
+const DEFAULT_BUTTONS: [(u8, &str); 6] = [
+    (0x5, "dpup"),
+    (0x7, "dpleft"),
+    (0x8, "dpdown"),
+    (0x9, "dpright"),
+    (0x6, "a"),
+    (0x4, "b")
+];
+
+// The directions and buttons a ROM database entry can give CHIP-8 keys for, with the host key and
+// controller button used for each.
+const ROLES: [(&str, &str, &str); 6] = [
+    ("up", "Up", "dpup"),
+    ("down", "Down", "dpdown"),
+    ("left", "Left", "dpleft"),
+    ("right", "Right", "dpright"),
+    ("a", "Space", "a"),
+    ("b", "Left Shift", "b")
+];
+
+// Keys the frontend handles itself before CHIP-8 input, so binding them would never reach the ROM.
+const HOTKEYS: [&str; 11] = ["Escape", "Backspace", "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9"];
+
+#[derive(Debug)]
+pub enum KeyConfigError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    // The file parsed, but the bindings it ends up with cannot all work.
+    Conflict(String)
+}
+
+impl fmt::Display for KeyConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyConfigError::Io(error) => write!(f, "{}", error),
+            KeyConfigError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            KeyConfigError::Conflict(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for KeyConfigError {}
+
+impl From<io::Error> for KeyConfigError {
+    fn from(error: io::Error) -> KeyConfigError {
+        return KeyConfigError::Io(error);
+    }
+}
+
+// Host key and controller button names for each CHIP-8 key. A key can have any number of them,
+// including none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KeyBindings {
+    pub keys: [Vec<String>; 16],
+    pub buttons: [Vec<String>; 16]
+}
+
+impl KeyBindings {
+    pub fn defaults() -> KeyBindings {
+        let mut bindings = KeyBindings::default();
+        for (row, names) in KEYPAD_ROWS.iter().zip(DEFAULT_KEYS.iter()) {
+            for (&key, &name) in row.iter().zip(names.iter()) {
+                bindings.keys[key as usize] = vec![String::from(name)];
+            }
+        }
+        for &(key, name) in DEFAULT_BUTTONS.iter() {
+            bindings.buttons[key as usize].push(String::from(name));
+        }
+        return bindings;
+    }
+
+    // Binds the host key and button for each role to the CHIP-8 key the ROM uses for it, taking them
+    // off any other key. Keyboard keys in the keypad layout stay where they are.
+    pub fn assign_roles(&mut self, roles: &[(String, u8)]) {
+        for (role, key) in roles.iter() {
+            let (host_key, button) = match ROLES.iter().find(|(name, _, _)| name == role) {
+                Some(&(_, host_key, button)) => (host_key, button),
+                None => continue
+            };
+            for names in self.keys.iter_mut() {
+                names.retain(|name| name != host_key);
+            }
+            for names in self.buttons.iter_mut() {
+                names.retain(|name| name != button);
+            }
+            self.keys[*key as usize].push(String::from(host_key));
+            self.buttons[*key as usize].push(String::from(button));
+        }
+    }
+
+    // Every host key and button must go to one CHIP-8 key, and host keys must not be hotkeys. SDL
+    // matches names without regard to case, so these checks do too.
+    pub fn check(&self) -> Result<(), KeyConfigError> {
+        let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+        for (key, names) in self.keys.iter().enumerate() {
+            for name in names.iter() {
+                if HOTKEYS.iter().any(|hotkey| hotkey.eq_ignore_ascii_case(name)) {
+                    return Err(KeyConfigError::Conflict(format!(
+                        "Host key '{}' for CHIP-8 key {:X} is a Rip8 hotkey", name, key
+                    )));
+                }
+                if let Some(other) = seen.insert(name.to_ascii_lowercase(), key).filter(|&other| other != key) {
+                    return Err(KeyConfigError::Conflict(format!(
+                        "Host key '{}' is bound to both CHIP-8 key {:X} and {:X}", name, other, key
+                    )));
+                }
+            }
+        }
+        let mut seen: BTreeMap<String, usize> = BTreeMap::new();
+        for (key, names) in self.buttons.iter().enumerate() {
+            for name in names.iter() {
+                if let Some(other) = seen.insert(name.to_ascii_lowercase(), key).filter(|&other| other != key) {
+                    return Err(KeyConfigError::Conflict(format!(
+                        "Controller button '{}' is bound to both CHIP-8 key {:X} and {:X}", name, other, key
+                    )));
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // The keypad as a 4x4 grid for the keyboard, then another for controllers.
+    pub fn describe(&self) -> String {
+        return format!("Keyboard\n{}\nController\n{}", keypad_grid(&self.keys), keypad_grid(&self.buttons));
+    }
+}
+
+// One CHIP-8 key and the names bound to it per cell.
+fn keypad_grid(bindings: &[Vec<String>; 16]) -> String {
+    let cells: Vec<Vec<String>> = KEYPAD_ROWS.iter()
+        .map(|row| row.iter().map(|&key| {
+            let names = &bindings[key as usize];
+            let names = if names.is_empty() { String::from("-") } else { names.join(", ") };
+            format!("{:X}: {}", key, names)
+        }).collect())
+        .collect();
+    let width = cells.iter().flatten().map(|cell| cell.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for row in cells.iter() {
+        let line: Vec<String> = row.iter().map(|cell| format!("{:<1$}", cell, width)).collect();
+        writeln!(out, "{}", line.join("   ").trim_end()).unwrap();
+    }
+    return out;
+}
+
+// A key mapping file. Lines before any section, or in [default], apply to every ROM; a section named
+// after a ROM file overrides the keys it lists when that ROM runs:
+//   # CHIP-8 key = host keys, by SDL scancode name
+//   # pad CHIP-8 key = controller buttons, by SDL button name
+//   [default]
+//   5 = W, Up
+//   [pong.ch8]
+//   1 = Tab
+//   C =
+//   pad 1 = x
+// An empty list leaves a key unbound. Keyboard and controller bindings are overridden separately.
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyConfig {
+    sections: BTreeMap<String, Section>
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Section {
+    keys: BTreeMap<u8, Vec<String>>,
+    buttons: BTreeMap<u8, Vec<String>>
+}
+
+impl KeyConfig {
+    pub fn parse(text: &str) -> Result<KeyConfig, KeyConfigError> {
+        let error = |line: usize, message: String| KeyConfigError::Parse { line, message };
+        let mut sections: BTreeMap<String, Section> = BTreeMap::new();
+        let mut section = String::from("default");
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                section = String::from(name.trim());
+                continue;
+            }
+            let (key, names) = line.split_once('=')
+                .ok_or_else(|| error(index + 1, format!("Expected 'key = host keys', got '{}'", line)))?;
+            let (pad, key) = match key.trim().strip_prefix("pad ") {
+                Some(key) => (true, key.trim()),
+                None => (false, key.trim())
+            };
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(error(index + 1, format!("Invalid CHIP-8 key '{}'", key)))
+            };
+            let names = names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty())
+                .map(String::from).collect();
+            let entry = sections.entry(section.clone()).or_default();
+            if pad {
+                entry.buttons.insert(key, names);
+            } else {
+                entry.keys.insert(key, names);
+            }
+        }
+        return Ok(KeyConfig { sections });
+    }
+
+    pub fn load(path: &Path) -> Result<KeyConfig, KeyConfigError> {
+        return KeyConfig::parse(&fs::read_to_string(path)?);
+    }
+
+    // The built-in defaults, then [default], then the roles from the ROM's database entry, then the
+    // section for the ROM. Fails if the result binds a key twice or binds a hotkey.
+    pub fn bindings(&self, rom_name: Option<&str>, roles: &[(String, u8)]) -> Result<KeyBindings, KeyConfigError> {
+        let mut bindings = KeyBindings::defaults();
+        if let Some(section) = self.sections.get("default") {
+            section.apply(&mut bindings);
+        }
+        bindings.assign_roles(roles);
+        if let Some(section) = rom_name.and_then(|name| self.sections.get(name)) {
+            section.apply(&mut bindings);
+        }
+        bindings.check()?;
+        return Ok(bindings);
+    }
+}
+
+impl Section {
+    fn apply(&self, bindings: &mut KeyBindings) {
+        for (&key, names) in self.keys.iter() {
+            bindings.keys[key as usize] = names.clone();
+        }
+        for (&key, names) in self.buttons.iter() {
+            bindings.buttons[key as usize] = names.clone();
+        }
+    }
+}
+
+// The file to read: the one given on the command line, or keys.cfg in the configuration directory if
+// there is one. None means the built-in defaults.
+pub fn key_config_path(given: Option<&Path>) -> Option<PathBuf> {
+    if let Some(path) = given {
+        return Some(path.to_path_buf());
+    }
+    return config_dir().map(|dir| dir.join("keys.cfg")).filter(|path| path.exists());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_follow_the_keypad_layout() {
+        let bindings = KeyBindings::defaults();
+        assert_eq!(bindings.keys[0x1], vec!["1"]);
+        assert_eq!(bindings.keys[0xC], vec!["4"]);
+        assert_eq!(bindings.keys[0x0], vec!["X"]);
+        assert_eq!(bindings.keys[0xF], vec!["V"]);
+        assert_eq!(bindings.buttons[0x5], vec!["dpup"]);
+    }
+
+    #[test]
+    fn rom_sections_override_the_default_section() {
+        let config = KeyConfig::parse("\
+            # Arrows as well as WASD-style keys\n\
+            5 = W, Up\n\
+            [pong.ch8]\n\
+            1 = Tab\n\
+            C =\n\
+            pad 1 = x\n").unwrap();
+        let bindings = config.bindings(None, &[]).unwrap();
+        assert_eq!(bindings.keys[0x5], vec!["W", "Up"]);
+        assert_eq!(bindings.keys[0x1], vec!["1"]);
+        let pong = config.bindings(Some("pong.ch8"), &[]).unwrap();
+        assert_eq!(pong.keys[0x5], vec!["W", "Up"]);
+        assert_eq!(pong.keys[0x1], vec!["Tab"]);
+        assert!(pong.keys[0xC].is_empty());
+        assert_eq!(pong.buttons[0x1], vec!["x"]);
+        assert_eq!(pong.buttons[0x8], vec!["dpdown"]);
+        assert!(pong.describe().starts_with("Keyboard\n1: Tab"));
+    }
+
+    #[test]
+    fn database_roles_come_between_default_and_rom_sections() {
+        let config = KeyConfig::parse("pad 2 = x\n[maze.ch8]\npad 8 = y\n").unwrap();
+        let roles = vec![(String::from("up"), 0x2u8), (String::from("down"), 0x5u8), (String::from("jump"), 0x1u8)];
+        let bindings = config.bindings(Some("maze.ch8"), &roles).unwrap();
+        assert_eq!(bindings.keys[0x2], vec!["2", "Up"]);
+        assert_eq!(bindings.keys[0x5], vec!["W", "Down"]);
+        assert_eq!(bindings.buttons[0x2], vec!["x", "dpup"]);
+        assert_eq!(bindings.buttons[0x5], vec!["dpdown"]);
+        assert_eq!(bindings.buttons[0x8], vec!["y"]);
+        assert!(bindings.keys[0x1] == vec!["1"] && bindings.buttons[0x1].is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let error = KeyConfig::parse("[default]\nG = Q\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 2: Invalid CHIP-8 key 'G'");
+        assert!(KeyConfig::parse("Q\n").is_err());
+    }
+
+    #[test]
+    fn rejects_conflicting_bindings() {
+        let conflict = |text: &str| KeyConfig::parse(text).unwrap().bindings(None, &[]).err().unwrap().to_string();
+        assert_eq!(conflict("6 = w\n"), "Host key 'w' is bound to both CHIP-8 key 5 and 6");
+        assert_eq!(conflict("pad 1 = A\n"), "Controller button 'a' is bound to both CHIP-8 key 1 and 6");
+        assert_eq!(conflict("1 = f5\n"), "Host key 'f5' for CHIP-8 key 1 is a Rip8 hotkey");
+        assert!(KeyConfig::parse("5 = W, W\n").unwrap().bindings(None, &[]).is_ok());
+    }
+}
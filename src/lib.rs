@@ -0,0 +1,24 @@
+#![allow(clippy::needless_return)]
+
+pub mod machine;
+pub mod movie;
+pub mod assembler;
+pub mod config;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod dump;
+pub mod execute;
+pub mod fault;
+pub mod hooks;
+pub mod instruction;
+pub mod key_event;
+pub mod keymap;
+pub mod palette;
+pub mod quirks;
+pub mod rewind;
+pub mod rom;
+pub mod rom_database;
+pub mod save_state;
+pub mod scheduler;
+pub mod sound;
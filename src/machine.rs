@@ -1,7 +1,45 @@
 use std::sync::{Arc, Mutex};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use crate::display::Display;
+use crate::execute::execute;
+use crate::fault::{CpuFault, StepOutcome};
+use crate::hooks::Hooks;
+use crate::quirks::Quirks;
+use crate::sound::Sound;
+
+// SUPER-CHIP 8x10 digits, stored after the 4x5 font.
+const BIG_FONT_START: usize = 80;
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// Progress of an Fx0A key wait, checked again on every step so the CPU never blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyWait {
+    Idle,
+    Waiting,
+    // The key that ended the wait; Fx0A stores it and moves on.
+    Done(u8)
+}
 
 pub struct Machine {
-    pub memory: [u8; 4096],
+    pub memory: [u8; 0x10000],
     pub v: [u8; 16],
     pub i: u16,
     pub dt: Arc<Mutex<u8>>,
@@ -11,13 +49,24 @@ pub struct Machine {
     pub stack: [u16; 16],
     pub keys: [bool; 16],
     pub sprite_digits: [u16; 16],
-    pub display: Arc<Mutex<[bool; 64 * 32]>>
+    pub big_sprite_digits: [u16; 16],
+    pub rpl: [u8; 16],
+    pub exited: bool,
+    pub key_wait: KeyWait,
+    pub display: Arc<Mutex<Display>>,
+    pub planes: u8,
+    pub sound: Arc<Mutex<Sound>>,
+    pub vblank: Arc<Mutex<bool>>,
+    // ChaCha8 gives the same numbers for a seed on every platform and rand version, which seeded runs
+    // and movies rely on.
+    pub rng: ChaCha8Rng,
+    pub quirks: Quirks
 }
 
 impl Machine {
     pub fn init() -> Machine {
         let mut m = Machine {
-            memory: [0u8; 4096],
+            memory: [0u8; 0x10000],
             v: [0u8; 16],
             i: 0u16,
             dt: Arc::new(Mutex::new(0u8)),
@@ -27,7 +76,16 @@ impl Machine {
             stack: [0u16; 16],
             keys: [false; 16],
             sprite_digits: [0u16; 16],
-            display: Arc::new(Mutex::new([false; 64 * 32]))
+            big_sprite_digits: [0u16; 16],
+            rpl: [0u8; 16],
+            exited: false,
+            key_wait: KeyWait::Idle,
+            display: Arc::new(Mutex::new(Display::new())),
+            planes: 0b01u8,
+            sound: Arc::new(Mutex::new(Sound::new())),
+            vblank: Arc::new(Mutex::new(false)),
+            rng: ChaCha8Rng::from_entropy(),
+            quirks: Quirks::default()
         };
 
         m.sprite_digits[0x0] = 0u16;
@@ -142,6 +200,34 @@ impl Machine {
         m.memory[78] = 0x80u8;
         m.memory[79] = 0x80u8;
 
+        m.memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        for digit in 0..16 {
+            m.big_sprite_digits[digit] = (BIG_FONT_START + digit * 10) as u16;
+        }
+
         return m;
     }
-}
\ No newline at end of file
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    pub fn step(&mut self, hooks: &mut dyn Hooks) -> Result<StepOutcome, CpuFault> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        return execute(self, hooks);
+    }
+
+    // Called once per 1/60 s frame.
+    pub fn tick_timers(&self) {
+        tick_timer(&self.dt);
+        tick_timer(&self.st);
+        *self.vblank.lock().unwrap() = true;
+    }
+}
+
+pub fn tick_timer(timer: &Mutex<u8>) {
+    let mut timer = timer.lock().unwrap();
+    *timer = timer.saturating_sub(1);
+}
@@ -1,111 +1,408 @@
-mod machine;
-mod execute;
-mod key_event;
-mod draw_event;
+#![allow(clippy::needless_return)]
 
-use crate::machine::Machine;
-use crate::execute::execute;
+mod audio;
+mod debug_console;
+mod fault_event;
+mod frame;
+mod keyboard;
+mod options;
+mod persistence;
+mod renderer;
+mod sdl_hooks;
+mod state_slots;
+mod window_geometry;
+
+use rip_8::machine::Machine;
+use rip_8::rom::load_rom_data;
+use rip_8::rom_database::load_rom_database;
+use std::fs;
 use std::thread;
-use std::time::Duration;
-use std::fs::File;
-use std::io::{Read};
-use std::cmp::min;
+use std::time::Instant;
+use std::env;
+use std::process;
+use std::collections::HashMap;
 use std::sync::mpsc::channel;
-use crate::key_event::{KeyEvent, handle_key_press};
-use sdl2::pixels::Color;
+use crate::keyboard::KeyMap;
+use rip_8::keymap::{KeyConfig, key_config_path};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::rect::Rect;
-use crate::draw_event::DrawEvent;
-use sdl2::timer::{TimerCallback};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::video::FullscreenType;
+use crate::fault_event::{FaultAction, FaultEvent};
+use sdl2::messagebox::{ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag, show_message_box};
+use crate::options::{Options, USAGE};
+use crate::renderer::Renderer;
+use crate::persistence::Persistence;
+use crate::frame::Frame;
+use crate::window_geometry::WindowGeometry;
+use crate::sdl_hooks::SdlHooks;
+use crate::audio::open_beeper;
+use crate::debug_console::{ConsoleAction, run_console};
+use rip_8::debugger::Debugger;
+use rip_8::hooks::Hooks;
+use rip_8::movie::{Movie, MovieHooks, MovieMode};
+use rip_8::palette::{Palette, PALETTE_NAMES};
+use rip_8::rewind::Rewind;
+use rip_8::save_state::SaveState;
+use rip_8::scheduler::FrameScheduler;
+use std::sync::{Arc, Mutex};
+use crate::state_slots::{SLOT_COUNT, StateRequest, handle_state_request};
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+    let mut options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("rip_8: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
     let (key_sender, key_receiver) = channel();
+    let rom_data = match fs::read(&options.rom_path) {
+        Ok(rom_data) => rom_data,
+        Err(error) => {
+            eprintln!("rip_8: Could not load ROM {}: {}", options.rom_path.display(), error);
+            process::exit(1);
+        }
+    };
+    let rom_database = match load_rom_database() {
+        Ok(rom_database) => rom_database,
+        Err(message) => {
+            eprintln!("rip_8: {}", message);
+            process::exit(1);
+        }
+    };
+    let rom_info = rom_database.lookup(&rom_data);
     let mut machine = Machine::init();
-    let display = machine.display.clone();
-    let dt = machine.dt.clone();
+    // A movie replays under the quirks it was recorded with, and only with the same ROM.
+    machine.quirks = match &options.movie {
+        Some(movie) if !movie.matches_rom(&rom_data) => {
+            eprintln!("rip_8: The movie was recorded with a different ROM (SHA-1 {})", movie.rom_hash);
+            process::exit(1);
+        }
+        Some(movie) => movie.quirks,
+        None => options.quirks_for(rom_info)
+    };
+    let palette = options.palette_for(rom_info);
+    let instructions_per_frame = options.instructions_per_frame_for(rom_info);
+    if let Err(error) = load_rom_data(&mut machine, &rom_data) {
+        eprintln!("rip_8: Could not load ROM {}: {}", options.rom_path.display(), error);
+        process::exit(1);
+    }
     let st = machine.st.clone();
 
+    let key_config = match key_config_path(options.key_config_path.as_deref()) {
+        Some(path) => match KeyConfig::load(&path) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!("rip_8: Could not load key mapping {}: {}", path.display(), error);
+                process::exit(1);
+            }
+        },
+        None => KeyConfig::default()
+    };
+    let rom_name = options.rom_path.file_name().and_then(|name| name.to_str());
+    let roles = rom_info.map_or(&[][..], |info| info.keys.as_slice());
+    let bindings = match key_config.bindings(rom_name, roles) {
+        Ok(bindings) => bindings,
+        Err(error) => {
+            eprintln!("rip_8: Invalid key mapping: {}", error);
+            process::exit(1);
+        }
+    };
+    let mut key_map = match KeyMap::new(&bindings) {
+        Ok(key_map) => key_map,
+        Err(message) => {
+            eprintln!("rip_8: {}", message);
+            process::exit(1);
+        }
+    };
+    let sound = machine.sound.clone();
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
     let event = sdl_context.event().unwrap();
-    event.register_custom_event::<DrawEvent>().unwrap();
+    event.register_custom_event::<FaultEvent>().unwrap();
     let event_sender = event.event_sender();
+    let controller_subsystem = sdl_context.game_controller().unwrap();
+    // Open controllers by instance id. SDL reports the ones already plugged in as added at startup.
+    let mut controllers = HashMap::new();
+    let mut beeper = open_beeper(&sdl_context, options.audio, st.clone(), sound);
 
-    thread::spawn(move || {
-        let mut rom = File::open("roms/Breakout (Brix hack) [David Winter, 1997].ch8").unwrap();
-        let mut rom_data = Vec::new();
-        rom.read_to_end(&mut rom_data).unwrap();
-
-        for i in 0..(min(rom_data.len(), 0x1000-0x200)) {
-            machine.memory[0x200 + i] = rom_data[i];
+    let pause_requested = Arc::new(Mutex::new(options.debug));
+    let cpu_pause_requested = pause_requested.clone();
+    let state_request: Arc<Mutex<Option<StateRequest>>> = Arc::new(Mutex::new(None));
+    let cpu_state_request = state_request.clone();
+    let rom_path = options.rom_path.clone();
+    let rewinding = Arc::new(Mutex::new(false));
+    let cpu_rewinding = rewinding.clone();
+    let frame = Arc::new(Mutex::new(Frame::new()));
+    let cpu_frame = frame.clone();
+    let (fault_action_sender, fault_action_receiver) = channel();
+    let rewind_capacity = options.rewind_seconds as usize * 60;
+    let instructions_per_frame = match &options.movie {
+        Some(movie) => movie.instructions_per_frame,
+        None => instructions_per_frame
+    };
+    // Movies need a known seed to replay the same way.
+    let recording = options.record_path.as_ref()
+        .map(|_| Arc::new(Mutex::new(Movie::new(&rom_data, rand::random::<u64>(), instructions_per_frame, machine.quirks))));
+    let movie_mode = match (options.movie.take(), &recording) {
+        (Some(movie), _) => {
+            machine.seed_rng(movie.seed);
+            MovieMode::Playing(movie.events.into_iter().collect())
         }
-        machine.pc = 0x200u16;
+        (None, Some(recording)) => {
+            machine.seed_rng(recording.lock().unwrap().seed);
+            MovieMode::Recording(recording.clone())
+        }
+        (None, None) => MovieMode::Off
+    };
+    // A movie is a list of key events at instruction counts from the start, so anything that replaces the
+    // machine state mid-run (loading a state, rewinding, resetting after a fault) is off while one is used.
+    let movie_active = !matches!(movie_mode, MovieMode::Off);
+    thread::spawn(move || {
+        let mut hooks = MovieHooks::new(SdlHooks { key_receiver, frame: cpu_frame }, movie_mode);
+        let mut debugger = Debugger::new();
+        let mut steps_left = 0u32;
+        let mut rewind = Rewind::new(rewind_capacity);
+        let mut instruction = 0u64;
+        let initial_state = SaveState::capture(&machine);
+        // Each frame runs a fixed number of instructions, then ticks the timers and presents the display.
+        let mut scheduler = FrameScheduler::new(Instant::now());
+        while !machine.exited {
+            if let Some(request) = cpu_state_request.lock().unwrap().take() {
+                if handle_state_request(&mut machine, &rom_path, request) {
+                    hooks.display_updated();
+                }
+            }
+            if *cpu_rewinding.lock().unwrap() {
+                // Step back one frame at a time. Keys stay as they are on the host keyboard.
+                if let Some(state) = rewind.pop() {
+                    let keys = machine.keys;
+                    state.restore(&mut machine);
+                    machine.keys = keys;
+                    hooks.display_updated();
+                }
+                hooks.inner.present(&machine.display);
+                thread::sleep(scheduler.end_frame(Instant::now()));
+                continue;
+            }
+            if std::mem::replace(&mut *cpu_pause_requested.lock().unwrap(), false) {
+                println!("Paused");
+                debugger.paused = true;
+            }
+            if !debugger.paused {
+                if let Some(reason) = debugger.check_breakpoints(&machine) {
+                    println!("{}", reason);
+                    debugger.paused = true;
+                }
+            }
+            if debugger.paused && steps_left == 0 {
+                match run_console(&mut machine, &mut debugger) {
+                    ConsoleAction::Step(n) => steps_left = n,
+                    ConsoleAction::Continue => {}
+                    ConsoleAction::Quit => break
+                }
+            }
 
-        loop {
-            execute(&mut machine, &key_receiver, &event_sender);
-            thread::sleep(Duration::from_millis(2));
+            let before = debugger.watch(&machine);
+            hooks.instruction = instruction;
+            if let Err(fault) = machine.step(&mut hooks) {
+                // Leave the machine as it was before the faulting instruction and let the user decide.
+                event_sender.push_custom_event(FaultEvent { fault }).unwrap();
+                match fault_action_receiver.recv() {
+                    Ok(FaultAction::Reset) => {
+                        initial_state.restore(&mut machine);
+                        rewind.clear();
+                        hooks.display_updated();
+                    }
+                    Ok(FaultAction::Debug) => {
+                        debugger.paused = true;
+                        steps_left = 0;
+                    }
+                    Ok(FaultAction::Quit) | Err(_) => break
+                }
+                continue;
+            }
+            steps_left = steps_left.saturating_sub(1);
+            instruction += 1;
+            if let Some(reason) = debugger.check_watchpoints(&before, &machine) {
+                println!("{}", reason);
+                debugger.paused = true;
+                steps_left = 0;
+            }
+            if instruction.is_multiple_of(instructions_per_frame as u64) {
+                machine.tick_timers();
+                rewind.push(SaveState::capture(&machine));
+                hooks.inner.present(&machine.display);
+                thread::sleep(scheduler.end_frame(Instant::now()));
+            } else if debugger.paused {
+                // Show every step while single-stepping in the debugger.
+                hooks.inner.present(&machine.display);
+            }
         }
+        event_sender.push_event(Event::Quit { timestamp: 0 }).unwrap();
     });
 
-    let window = video_subsystem
-        .window("Rip8", 640, 320)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
+    // An explicit --scale wins over the size the window was last closed at.
+    let saved_geometry = if options.scale.is_none() { WindowGeometry::load() } else { None };
+    let scale = options.scale.unwrap_or(10u32);
+    let title = match rom_info.and_then(|info| info.title.as_deref()) {
+        Some(rom_title) => format!("Rip8 - {}", rom_title),
+        None => String::from("Rip8")
+    };
+    let mut window_builder = match saved_geometry {
+        Some(geometry) => video_subsystem.window(&title, geometry.width, geometry.height),
+        None => video_subsystem.window(&title, 64 * scale, 32 * scale)
+    };
+    match saved_geometry {
+        Some(geometry) => window_builder.position(geometry.x, geometry.y),
+        None => window_builder.position_centered()
+    };
+    let window = window_builder.resizable().opengl().build().unwrap();
+    // Where the window was before going fullscreen, so that is what gets saved.
+    let mut windowed_geometry = None;
 
-    canvas.set_draw_color(Color::BLACK);
-    canvas.clear();
-    canvas.present();
+    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let persistence = Persistence::new(options.anti_flicker, options.fade_frames);
+    let mut renderer = Renderer::new(&texture_creator, palette, persistence);
+    renderer.grid = options.grid;
+    let mut palette_index = PALETTE_NAMES.iter().position(|name| Palette::from_name(name) == Some(palette));
 
-    let timer = sdl_context.timer().unwrap();
-    let _t = timer.add_timer(1000_000 / 60_000, TimerCallback::from(Box::new(|| {
-        let mut dt = dt.lock().unwrap();
-        let mut st = st.lock().unwrap();
-        *dt = dt.checked_sub(1).unwrap_or(0);
-        *st = st.checked_sub(1).unwrap_or(0);
-        return 1000_000 / 60_000;
-    })));
-
-    for event in event_pump.wait_iter() {
-        match event {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
-                ..
-            } => break,
-            Event::KeyDown { keycode: Some(key_code), .. } => handle_key_press(&key_sender, key_code, true),
-            Event::KeyUp { keycode: Some(key_code), .. } => handle_key_press(&key_sender, key_code, false),
-            Event::User { .. } => {
-                canvas.set_draw_color(Color::BLACK);
-                canvas.clear();
-                let (width, height) = canvas.output_size().unwrap();
-                let pixel_width = width / 64;
-                let pixel_height = height / 32;
-                let display = display.lock().unwrap();
-                for y in 0..32 {
-                    for x in 0..64 {
-                        let color = if display[x * 32 + y] {
-                            Color::WHITE
-                        } else {
-                            Color::BLACK
-                        };
-                        canvas.set_draw_color(color);
-                        canvas.fill_rect(Rect::new(
-                            x as i32 * pixel_width as i32,
-                            y as i32 * pixel_height as i32,
-                            pixel_width,
-                            pixel_height
-                        )).unwrap();
+    let mut slot = 1u8;
+    // Vsync keeps presentation in step with the monitor; the scheduler caps it at 60 frames per second
+    // when vsync is unavailable or the monitor refreshes faster.
+    let mut scheduler = FrameScheduler::new(Instant::now());
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            if let Some(FaultEvent { fault }) = event.as_user_event_type::<FaultEvent>() {
+                eprintln!("rip_8: {}", fault);
+                let mut buttons = vec![
+                    ButtonData { flags: MessageBoxButtonFlag::RETURNKEY_DEFAULT, button_id: 0, text: "Reset" },
+                    ButtonData { flags: MessageBoxButtonFlag::NOTHING, button_id: 1, text: "Debugger" },
+                    ButtonData { flags: MessageBoxButtonFlag::ESCAPEKEY_DEFAULT, button_id: 2, text: "Quit" }
+                ];
+                if movie_active {
+                    buttons.remove(0);
+                }
+                let message = format!("{}", fault);
+                let action = match show_message_box(MessageBoxFlag::ERROR, &buttons, "CPU fault", &message, canvas.window(), None) {
+                    Ok(ClickedButton::CustomButton(button)) if button.button_id == 0 => FaultAction::Reset,
+                    Ok(ClickedButton::CustomButton(button)) if button.button_id == 1 => FaultAction::Debug,
+                    _ => FaultAction::Quit
+                };
+                fault_action_sender.send(action).unwrap();
+                continue;
+            }
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown { keycode: Some(Keycode::Return), keymod, repeat: false, .. }
+                    if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                    let window = canvas.window_mut();
+                    if window.fullscreen_state() == FullscreenType::Off {
+                        windowed_geometry = Some(WindowGeometry::of(window));
+                        window.set_fullscreen(FullscreenType::Desktop).unwrap();
+                    } else {
+                        window.set_fullscreen(FullscreenType::Off).unwrap();
+                        windowed_geometry = None;
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => {
+                    *state_request.lock().unwrap() = Some(StateRequest::Save(slot));
+                }
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                    slot = (slot + SLOT_COUNT - 1) % SLOT_COUNT;
+                    println!("State slot {}", slot);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                    slot = (slot + 1) % SLOT_COUNT;
+                    println!("State slot {}", slot);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F4), repeat: false, .. } => {
+                    if movie_active {
+                        println!("Loading states is disabled while a movie is recording or playing");
+                    } else {
+                        *state_request.lock().unwrap() = Some(StateRequest::Load(slot));
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::Backspace), repeat: false, .. } => {
+                    if movie_active {
+                        println!("Rewinding is disabled while a movie is recording or playing");
+                    } else {
+                        *rewinding.lock().unwrap() = true;
                     }
                 }
-                canvas.present();
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => {
+                    *rewinding.lock().unwrap() = false;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    *pause_requested.lock().unwrap() = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } => {
+                    let next = palette_index.map_or(0, |index| (index + 1) % PALETTE_NAMES.len());
+                    palette_index = Some(next);
+                    renderer.set_palette(Palette::from_name(PALETTE_NAMES[next]).unwrap());
+                    println!("Palette {}", PALETTE_NAMES[next]);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F7), repeat: false, .. } => {
+                    let mode = renderer.anti_flicker().next();
+                    renderer.set_anti_flicker(mode);
+                    println!("Anti-flicker {}", mode.name());
+                }
+                Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => {
+                    if let Some(beeper) = beeper.as_mut() {
+                        let mut beeper = beeper.lock();
+                        beeper.settings.muted = !beeper.settings.muted;
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    renderer.grid = !renderer.grid;
+                }
+                Event::KeyDown { scancode: Some(scancode), repeat: false, .. } => {
+                    key_map.handle_key_press(&key_sender, scancode, true);
+                }
+                Event::KeyUp { scancode: Some(scancode), .. } => key_map.handle_key_press(&key_sender, scancode, false),
+                Event::ControllerDeviceAdded { which, .. } => match controller_subsystem.open(which) {
+                    Ok(controller) => {
+                        println!("Controller connected: {}", controller.name());
+                        controllers.insert(controller.instance_id(), controller);
+                    }
+                    Err(error) => eprintln!("rip_8: Could not open controller {}: {}", which, error)
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    key_map.release_controller(&key_sender, which);
+                    if let Some(controller) = controllers.remove(&which) {
+                        println!("Controller disconnected: {}", controller.name());
+                    }
+                }
+                Event::ControllerButtonDown { which, button, .. } => key_map.handle_button_press(&key_sender, which, button, true),
+                Event::ControllerButtonUp { which, button, .. } => key_map.handle_button_press(&key_sender, which, button, false),
+                _ => {}
             }
-            _ => {}
+        }
+        renderer.update(&frame);
+        renderer.present(&mut canvas);
+        thread::sleep(scheduler.end_frame(Instant::now()));
+    }
+    let geometry = windowed_geometry.unwrap_or_else(|| WindowGeometry::of(canvas.window()));
+    if let Err(error) = geometry.save() {
+        eprintln!("rip_8: Could not save the window position: {}", error);
+    }
+    if let (Some(recording), Some(record_path)) = (&recording, &options.record_path) {
+        match recording.lock().unwrap().save(record_path) {
+            Ok(()) => println!("Saved movie to {}", record_path.display()),
+            Err(error) => eprintln!("rip_8: Could not save movie to {}: {}", record_path.display(), error)
         }
     }
 }
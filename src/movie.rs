@@ -0,0 +1,340 @@
+use crate::hooks::Hooks;
+use crate::key_event::KeyEvent;
+use crate::quirks::{LoadStoreIncrement, Quirks};
+use crate::rom_database::rom_hash;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const HEADER: &str = "rip8-movie 3";
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Parse { line: usize, message: String }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(f, "{}", error),
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> MovieError {
+        return MovieError::Io(error);
+    }
+}
+
+// A key event and the instruction at which the machine consumed it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MovieEvent {
+    pub instruction: u64,
+    pub key_event: KeyEvent
+}
+
+// Everything needed to replay a run: the ROM it was recorded with (by SHA-1), the RNG seed, the number
+// of instructions between timer ticks, the quirks, and the input.
+#[derive(Debug, PartialEq)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    pub quirks: Quirks,
+    pub events: Vec<MovieEvent>
+}
+
+impl Movie {
+    pub fn new(rom_data: &[u8], seed: u64, instructions_per_frame: u32, quirks: Quirks) -> Movie {
+        return Movie { rom_hash: rom_hash(rom_data), seed, instructions_per_frame, quirks, events: Vec::new() };
+    }
+
+    pub fn matches_rom(&self, rom_data: &[u8]) -> bool {
+        return rom_hash(rom_data) == self.rom_hash;
+    }
+
+    // Plain text so movies can be attached to bug reports and read in a diff:
+    //   rip8-movie 3
+    //   rom <SHA-1 of the ROM file>
+    //   seed 1234
+    //   ipf 8
+    //   quirks shift_uses_vy=1 load_store_increment=x+1 jump_uses_vx=0 ... memory_size=0x1000
+    //   1200 5 down
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "{}", HEADER).unwrap();
+        writeln!(out, "rom {}", self.rom_hash).unwrap();
+        writeln!(out, "seed {}", self.seed).unwrap();
+        writeln!(out, "ipf {}", self.instructions_per_frame).unwrap();
+        writeln!(out, "quirks {}", quirks_text(&self.quirks)).unwrap();
+        for event in self.events.iter() {
+            writeln!(
+                out,
+                "{} {:X} {}",
+                event.instruction,
+                event.key_event.key,
+                if event.key_event.pressed { "down" } else { "up" }
+            ).unwrap();
+        }
+        return out;
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text.lines().enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let error = |line: usize, message: String| MovieError::Parse { line, message };
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((line, _)) => return Err(error(line, format!("Expected '{}'", HEADER))),
+            None => return Err(error(1, String::from("Movie is empty")))
+        }
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut instructions_per_frame = None;
+        let mut quirks = None;
+        let mut events = Vec::new();
+        for (line, text) in lines {
+            let fields: Vec<&str> = text.split_whitespace().collect();
+            match fields.as_slice() {
+                ["rom", value] => {
+                    if value.len() != 40 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(error(line, format!("Invalid ROM SHA-1 '{}'", value)));
+                    }
+                    rom_hash = Some(value.to_ascii_lowercase());
+                }
+                ["seed", value] => {
+                    seed = Some(value.parse::<u64>().map_err(|_| error(line, format!("Invalid seed '{}'", value)))?);
+                }
+                ["ipf", value] => match value.parse::<u32>() {
+                    Ok(n) if n > 0 => instructions_per_frame = Some(n),
+                    _ => return Err(error(line, format!("Invalid instructions per frame '{}'", value)))
+                },
+                ["quirks", settings @ ..] => {
+                    quirks = Some(parse_quirks(settings).map_err(|message| error(line, message))?);
+                }
+                [instruction, key, state] => {
+                    let instruction = instruction.parse::<u64>()
+                        .map_err(|_| error(line, format!("Invalid instruction number '{}'", instruction)))?;
+                    let key = match u8::from_str_radix(key, 16) {
+                        Ok(key) if key < 16 => key,
+                        _ => return Err(error(line, format!("Invalid key '{}'", key)))
+                    };
+                    let pressed = match *state {
+                        "down" => true,
+                        "up" => false,
+                        _ => return Err(error(line, format!("Expected 'down' or 'up', got '{}'", state)))
+                    };
+                    if events.last().is_some_and(|last: &MovieEvent| last.instruction > instruction) {
+                        return Err(error(line, String::from("Events are out of order")));
+                    }
+                    events.push(MovieEvent { instruction, key_event: KeyEvent { key, pressed } });
+                }
+                _ => return Err(error(line, format!("Unexpected '{}'", text)))
+            }
+        }
+        let rom_hash = rom_hash.ok_or_else(|| error(1, String::from("Movie has no rom")))?;
+        let seed = seed.ok_or_else(|| error(1, String::from("Movie has no seed")))?;
+        let instructions_per_frame = instructions_per_frame
+            .ok_or_else(|| error(1, String::from("Movie has no ipf")))?;
+        let quirks = quirks.ok_or_else(|| error(1, String::from("Movie has no quirks")))?;
+        return Ok(Movie { rom_hash, seed, instructions_per_frame, quirks, events });
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, MovieError> {
+        return Movie::parse(&fs::read_to_string(path)?);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.to_text())?;
+        return Ok(());
+    }
+}
+
+fn quirks_text(quirks: &Quirks) -> String {
+    let increment = match quirks.load_store_increment {
+        LoadStoreIncrement::None => "0",
+        LoadStoreIncrement::X => "x",
+        LoadStoreIncrement::XPlusOne => "x+1"
+    };
+    return format!(
+        "shift_uses_vy={} load_store_increment={} jump_uses_vx={} logic_resets_vf={} clip_sprites={} \
+         display_wait={} key_wait_release={} memory_size=0x{:X}",
+        quirks.shift_uses_vy as u8, increment, quirks.jump_uses_vx as u8, quirks.logic_resets_vf as u8,
+        quirks.clip_sprites as u8, quirks.display_wait as u8, quirks.key_wait_release as u8, quirks.memory_size
+    );
+}
+
+// Every quirk must be given, so a movie never silently picks up whatever the defaults are at replay time.
+fn parse_quirks(settings: &[&str]) -> Result<Quirks, String> {
+    let mut values = HashMap::new();
+    for setting in settings.iter() {
+        let (name, value) = setting.split_once('=').ok_or_else(|| format!("Expected 'name=value', got '{}'", setting))?;
+        values.insert(name, value);
+    }
+    let value = |name: &str| values.get(name).copied().ok_or_else(|| format!("Missing quirk '{}'", name));
+    let flag = |name: &str| match value(name)? {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        other => Err(format!("Invalid value '{}' for quirk '{}'", other, name))
+    };
+    let load_store_increment = match value("load_store_increment")? {
+        "0" => LoadStoreIncrement::None,
+        "x" => LoadStoreIncrement::X,
+        "x+1" => LoadStoreIncrement::XPlusOne,
+        other => return Err(format!("Invalid value '{}' for quirk 'load_store_increment'", other))
+    };
+    let memory_size = value("memory_size")?;
+    let memory_size = match usize::from_str_radix(memory_size.trim_start_matches("0x"), 16) {
+        Ok(size) if (0x1000..=0x10000).contains(&size) => size,
+        _ => return Err(format!("Invalid value '{}' for quirk 'memory_size'", memory_size))
+    };
+    return Ok(Quirks {
+        shift_uses_vy: flag("shift_uses_vy")?,
+        load_store_increment,
+        jump_uses_vx: flag("jump_uses_vx")?,
+        logic_resets_vf: flag("logic_resets_vf")?,
+        clip_sprites: flag("clip_sprites")?,
+        display_wait: flag("display_wait")?,
+        key_wait_release: flag("key_wait_release")?,
+        memory_size
+    });
+}
+
+pub enum MovieMode {
+    Off,
+    // Events are appended to the shared movie so the frontend can save it when the window closes.
+    Recording(Arc<Mutex<Movie>>),
+    // Input from the wrapped hooks is discarded and the movie's events are played instead.
+    Playing(VecDeque<MovieEvent>)
+}
+
+// Wraps a frontend's hooks to record or replay its input. The caller sets `instruction` to the
+// number of instructions executed so far before each step.
+pub struct MovieHooks<H: Hooks> {
+    pub inner: H,
+    pub instruction: u64,
+    pub mode: MovieMode
+}
+
+impl<H: Hooks> MovieHooks<H> {
+    pub fn new(inner: H, mode: MovieMode) -> MovieHooks<H> {
+        return MovieHooks { inner, instruction: 0u64, mode };
+    }
+
+    fn next_event(&mut self) -> Option<KeyEvent> {
+        let instruction = self.instruction;
+        let events = match &mut self.mode {
+            MovieMode::Playing(events) => events,
+            _ => return None
+        };
+        let due = events.front().is_some_and(|event| event.instruction <= instruction);
+        return if due { events.pop_front().map(|event| event.key_event) } else { None };
+    }
+}
+
+impl<H: Hooks> Hooks for MovieHooks<H> {
+    fn poll_key(&mut self) -> Option<KeyEvent> {
+        if let MovieMode::Playing(_) = self.mode {
+            while self.inner.poll_key().is_some() {}
+            return self.next_event();
+        }
+        let key_event = self.inner.poll_key();
+        if let (MovieMode::Recording(movie), Some(key_event)) = (&self.mode, key_event) {
+            movie.lock().unwrap().events.push(MovieEvent { instruction: self.instruction, key_event });
+        }
+        return key_event;
+    }
+
+    fn display_updated(&mut self) {
+        self.inner.display_updated();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+
+    // Input for the test. Each poll_key call takes one entry, so a None ends that step's polling.
+    struct Script {
+        polled: VecDeque<Option<KeyEvent>>
+    }
+
+    impl Hooks for Script {
+        fn poll_key(&mut self) -> Option<KeyEvent> {
+            return self.polled.pop_front().flatten();
+        }
+
+        fn display_updated(&mut self) {}
+    }
+
+    // Fx0A into V1, then count up V2 while key 5 is held.
+    fn machine() -> Machine {
+        let mut machine = Machine::init();
+        let program = [0xF1, 0x0A, 0x65, 0x05, 0xE5, 0xA1, 0x72, 0x01, 0x12, 0x04];
+        machine.memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+        machine.pc = 0x200;
+        return machine;
+    }
+
+    fn run(machine: &mut Machine, hooks: &mut MovieHooks<Script>, steps: u64) {
+        for n in 0..steps {
+            hooks.instruction = n;
+            machine.step(hooks).unwrap();
+        }
+    }
+
+    #[test]
+    fn playback_reproduces_a_recording() {
+        let movie = Arc::new(Mutex::new(Movie::new(&[0x12, 0x00], 7, 8, Quirks::cosmac_vip())));
+        // Fx0A waits two instructions for key 3. Key 5 then goes down and comes up ten instructions later.
+        let mut polled: VecDeque<Option<KeyEvent>> = VecDeque::new();
+        polled.extend(vec![None, None, Some(KeyEvent { key: 3, pressed: true }), None]);
+        polled.extend(vec![None, Some(KeyEvent { key: 5, pressed: true }), None]);
+        polled.extend(vec![None; 10]);
+        polled.extend(vec![Some(KeyEvent { key: 5, pressed: false })]);
+        let script = Script { polled };
+        let mut recorder = MovieHooks::new(script, MovieMode::Recording(movie.clone()));
+        let mut recorded = machine();
+        run(&mut recorded, &mut recorder, 40);
+        let text = movie.lock().unwrap().to_text();
+
+        let movie = Movie::parse(&text).unwrap();
+        assert_eq!(movie.quirks, Quirks::cosmac_vip());
+        assert!(movie.matches_rom(&[0x12, 0x00]));
+        assert!(!movie.matches_rom(&[0x12, 0x02]));
+        assert_eq!(movie.events[0], MovieEvent { instruction: 2, key_event: KeyEvent { key: 3, pressed: true } });
+        let script = Script { polled: VecDeque::new() };
+        let mut player = MovieHooks::new(script, MovieMode::Playing(movie.events.into_iter().collect()));
+        let mut played = machine();
+        run(&mut played, &mut player, 40);
+        assert_eq!(played.v, recorded.v);
+        assert_eq!(played.v[1], 3);
+        assert!(played.v[2] > 0);
+    }
+
+    #[test]
+    fn rejects_malformed_movies() {
+        let header = Movie::new(&[], 1, 8, Quirks::modern()).to_text();
+        assert!(Movie::parse(&header).is_ok());
+        assert!(Movie::parse("").is_err());
+        assert!(Movie::parse("rip8-movie 3\nipf 8\n").is_err());
+        let error = Movie::parse(&format!("{}5 3 down\n4 3 up\n", header)).err().unwrap();
+        assert_eq!(error.to_string(), "line 7: Events are out of order");
+        assert!(Movie::parse(&format!("{}5 10 down\n", header)).is_err());
+        let error = Movie::parse(&header.replace(" clip_sprites=0", "")).err().unwrap();
+        assert_eq!(error.to_string(), "line 5: Missing quirk 'clip_sprites'");
+        assert!(Movie::parse(&header.replace("key_wait_release=0", "key_wait_release=2")).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+use rip_8::movie::Movie;
+use rip_8::palette::{Palette, Rgb, PALETTE_NAMES};
+use rip_8::quirks::{Quirks, PROFILE_NAMES};
+use rip_8::rom_database::RomInfo;
+use crate::audio::{AudioSettings, Waveform};
+use crate::persistence::{AntiFlicker, ANTI_FLICKER_NAMES};
+
+pub const USAGE: &str = "\
+Usage: rip_8 [OPTIONS] <ROM>
+
+Options:
+    --ipf <N>           Instructions executed per 1/60 s frame (default: 8, or the ROM's tickrate)
+    --speed <HZ>        Instructions executed per second, rounded to a whole number per frame
+    --scale <N>         Initial window pixels per CHIP-8 pixel (default: 10, or the size the window
+                        had when Rip8 last closed)
+    --keys <PATH>       Keyboard and controller mapping file (default: keys.cfg in the configuration directory)
+    --grid              Draw lines between pixels (toggle with F9)
+    --quirks <NAME>     Interpreter to emulate: vip, chip48, schip, xochip or modern (default: the ROM's
+                        platform, or modern)
+    --key-wait <WHEN>   Finish Fx0A on key press or release (default: set by --quirks)
+    --palette <NAME>    Colours: classic, amber, green, lcd or high-contrast (default: classic), or two or
+                        four comma-separated RRGGBB colours, background first (default: the ROM's colours,
+                        or classic; cycle with F6)
+    --foreground <HEX>  Foreground colour as RRGGBB, overriding the palette's
+    --background <HEX>  Background colour as RRGGBB, overriding the palette's
+    --deflicker <MODE>  Hide sprite flicker: off, fade or blend (default: off, cycle with F7)
+    --fade-frames <N>   Emulated frames a pixel takes to fade out in fade mode (default: 4)
+    --tone <HZ>         Frequency of the sound timer beep (default: 440)
+    --volume <PERCENT>  Beep volume from 0 to 100 (default: 25)
+    --waveform <NAME>   Beep waveform: square, sine, triangle or sawtooth (default: square)
+    --mute              Start with sound muted (toggle with F8)
+    --debug             Start paused in the debugger console (pause any time with F5)
+    --record <PATH>     Record input to a movie file, saved when the window closes
+    --play <PATH>       Replay the input of a movie instead of reading the keyboard
+    --rewind <SECONDS>  How much history to keep for rewinding with Backspace, 0 to disable (default: 30)
+    -h, --help          Print this message
+
+Save states: F1 saves to the current slot, F4 loads it, F2/F3 select slots 0-9. Slot files are
+written next to the ROM. Loading states, rewinding and resetting after a fault are disabled while a
+movie is recording or playing, as they would put it out of step with the ROM.
+
+The window can be resized; Alt+Enter toggles fullscreen.
+
+ROMs are looked up by SHA-1 in programs.json from the CHIP-8 community database
+(https://github.com/chip-8/chip-8-database) and then user-programs.json, both in the configuration
+directory, for their title, platform, speed, colours and keys. Options given here take precedence.
+";
+
+pub struct Options {
+    pub rom_path: PathBuf,
+    pub instructions_per_frame: Option<u32>,
+    pub scale: Option<u32>,
+    pub grid: bool,
+    pub key_config_path: Option<PathBuf>,
+    pub quirks: Option<Quirks>,
+    pub key_wait_release: Option<bool>,
+    pub palette: Option<Palette>,
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+    pub anti_flicker: AntiFlicker,
+    pub fade_frames: u8,
+    pub audio: AudioSettings,
+    pub debug: bool,
+    pub rewind_seconds: u32,
+    pub record_path: Option<PathBuf>,
+    pub movie: Option<Movie>
+}
+
+impl Options {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut rom_path = None;
+        let mut instructions_per_frame = None;
+        let mut scale = None;
+        let mut grid = false;
+        let mut key_config_path = None;
+        let mut quirks = None;
+        let mut key_wait_release = None;
+        let mut palette = None;
+        let mut foreground = None;
+        let mut anti_flicker = AntiFlicker::Off;
+        let mut fade_frames = 4u8;
+        let mut background = None;
+        let mut debug = false;
+        let mut rewind_seconds = 30u32;
+        let mut record_path = None;
+        let mut movie = None;
+        let mut audio = AudioSettings { frequency: 440.0, volume: 0.25, waveform: Waveform::Square, muted: false };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ipf" => instructions_per_frame = Some(parse_positive(&arg, args.next())?),
+                "--speed" => instructions_per_frame = Some(std::cmp::max(1u32, (parse_positive(&arg, args.next())? + 30u32) / 60u32)),
+                "--scale" => scale = Some(parse_positive(&arg, args.next())?),
+                "--grid" => grid = true,
+                "--keys" => {
+                    let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                    key_config_path = Some(PathBuf::from(value));
+                }
+                "--quirks" => quirks = Some(parse_quirks(&arg, args.next())?),
+                "--key-wait" => key_wait_release = Some(parse_key_wait(&arg, args.next())?),
+                "--palette" => palette = Some(parse_palette(&arg, args.next())?),
+                "--foreground" => foreground = Some(parse_color(&arg, args.next())?),
+                "--background" => background = Some(parse_color(&arg, args.next())?),
+                "--deflicker" => anti_flicker = parse_anti_flicker(&arg, args.next())?,
+                "--fade-frames" => fade_frames = parse_fade_frames(&arg, args.next())?,
+                "--tone" => audio.frequency = parse_positive(&arg, args.next())? as f32,
+                "--volume" => audio.volume = parse_volume(&arg, args.next())?,
+                "--waveform" => audio.waveform = parse_waveform(&arg, args.next())?,
+                "--mute" => audio.muted = true,
+                "--debug" => debug = true,
+                "--record" => {
+                    let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                    record_path = Some(PathBuf::from(value));
+                }
+                "--play" => {
+                    let value = args.next().ok_or_else(|| format!("Option '{}' needs a value", arg))?;
+                    movie = Some(Movie::load(Path::new(&value))
+                        .map_err(|error| format!("Could not load movie {}: {}", value, error))?);
+                }
+                "--rewind" => rewind_seconds = parse_count(&arg, args.next())?,
+                _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+                _ if rom_path.is_some() => return Err(format!("Unexpected argument '{}'", arg)),
+                _ => rom_path = Some(PathBuf::from(arg))
+            }
+        }
+
+        if record_path.is_some() && movie.is_some() {
+            return Err(String::from("Options '--record' and '--play' cannot be used together"));
+        }
+        let rom_path = rom_path.ok_or_else(|| String::from("No ROM given"))?;
+        return Ok(Options {
+            rom_path, instructions_per_frame, scale, grid, key_config_path, quirks, key_wait_release, palette,
+            foreground, background, anti_flicker, fade_frames, audio, debug, rewind_seconds, record_path, movie
+        });
+    }
+
+    // The settings below are the options given, then the ROM's database entry, then the defaults.
+    pub fn quirks_for(&self, rom_info: Option<&RomInfo>) -> Quirks {
+        let mut quirks = self.quirks.or_else(|| rom_info.and_then(|info| info.quirks)).unwrap_or_default();
+        if let Some(key_wait_release) = self.key_wait_release {
+            quirks.key_wait_release = key_wait_release;
+        }
+        return quirks;
+    }
+
+    pub fn instructions_per_frame_for(&self, rom_info: Option<&RomInfo>) -> u32 {
+        return self.instructions_per_frame.or_else(|| rom_info.and_then(|info| info.instructions_per_frame))
+            .unwrap_or(8u32);
+    }
+
+    pub fn palette_for(&self, rom_info: Option<&RomInfo>) -> Palette {
+        let palette = self.palette.or_else(|| rom_info.and_then(|info| info.palette)).unwrap_or_default();
+        if self.foreground.is_none() && self.background.is_none() {
+            return palette;
+        }
+        return Palette::from_colors(
+            self.background.unwrap_or(palette.colors[0]),
+            self.foreground.unwrap_or(palette.colors[1])
+        );
+    }
+}
+
+fn parse_positive(option: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Option '{}' expects a positive number, got '{}'", option, value))
+    };
+}
+
+fn parse_count(option: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return value.parse::<u32>()
+        .map_err(|_| format!("Option '{}' expects a number, got '{}'", option, value));
+}
+
+fn parse_quirks(option: &str, value: Option<String>) -> Result<Quirks, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return Quirks::from_name(&value).ok_or_else(|| format!(
+        "Unknown quirk profile '{}', expected one of: {}",
+        value, PROFILE_NAMES.join(", ")
+    ));
+}
+
+// True when Fx0A should finish on release.
+fn parse_key_wait(option: &str, value: Option<String>) -> Result<bool, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return match value.as_str() {
+        "press" => Ok(false),
+        "release" => Ok(true),
+        _ => Err(format!("Option '{}' expects 'press' or 'release', got '{}'", option, value))
+    };
+}
+
+fn parse_palette(option: &str, value: Option<String>) -> Result<Palette, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return Palette::parse(&value).ok_or_else(|| format!(
+        "Unknown palette '{}', expected one of {} or a list of RRGGBB colours",
+        value, PALETTE_NAMES.join(", ")
+    ));
+}
+
+fn parse_color(option: &str, value: Option<String>) -> Result<Rgb, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return Rgb::parse(&value).ok_or_else(|| format!("Option '{}' expects an RRGGBB colour, got '{}'", option, value));
+}
+
+fn parse_anti_flicker(option: &str, value: Option<String>) -> Result<AntiFlicker, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return AntiFlicker::from_name(&value).ok_or_else(|| format!(
+        "Unknown deflicker mode '{}', expected one of: {}",
+        value, ANTI_FLICKER_NAMES.join(", ")
+    ));
+}
+
+fn parse_fade_frames(option: &str, value: Option<String>) -> Result<u8, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return match value.parse::<u8>() {
+        Ok(frames) if frames > 0 => Ok(frames),
+        _ => Err(format!("Option '{}' expects a number from 1 to 255, got '{}'", option, value))
+    };
+}
+
+fn parse_volume(option: &str, value: Option<String>) -> Result<f32, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return match value.parse::<u32>() {
+        Ok(percent) if percent <= 100 => Ok(percent as f32 / 100.0),
+        _ => Err(format!("Option '{}' expects a number from 0 to 100, got '{}'", option, value))
+    };
+}
+
+fn parse_waveform(option: &str, value: Option<String>) -> Result<Waveform, String> {
+    let value = value.ok_or_else(|| format!("Option '{}' needs a value", option))?;
+    return Waveform::from_name(&value).ok_or_else(|| format!(
+        "Unknown waveform '{}', expected one of: square, sine, triangle, sawtooth",
+        value
+    ));
+}
@@ -0,0 +1,100 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+impl Rgb {
+    pub const fn new(hex: u32) -> Rgb {
+        return Rgb { r: (hex >> 16) as u8, g: (hex >> 8) as u8, b: hex as u8 };
+    }
+
+    // "RRGGBB", with or without a leading '#'.
+    pub fn parse(text: &str) -> Option<Rgb> {
+        let digits = text.strip_prefix('#').unwrap_or(text);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return u32::from_str_radix(digits, 16).ok().map(Rgb::new);
+    }
+
+    // `amount` out of `total` of the way from self to other.
+    pub fn mix(self, other: Rgb, amount: u32, total: u32) -> Rgb {
+        let mix = |a: u8, b: u8| ((a as u32 * (total - amount) + b as u32 * amount) / total) as u8;
+        return Rgb { r: mix(self.r, other.r), g: mix(self.g, other.g), b: mix(self.b, other.b) };
+    }
+}
+
+// Colours for each combination of the two XO-CHIP bitplanes: background, plane 1, plane 2, both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [Rgb; 4]
+}
+
+pub const PALETTE_NAMES: [&str; 5] = ["classic", "amber", "green", "lcd", "high-contrast"];
+
+impl Palette {
+    // Plain CHIP-8 only uses the first two colours; the XO-CHIP ones are shades in between.
+    pub fn from_colors(background: Rgb, foreground: Rgb) -> Palette {
+        return Palette { colors: [background, foreground, background.mix(foreground, 2, 3), background.mix(foreground, 1, 3)] };
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        return match name.to_ascii_lowercase().as_str() {
+            "classic" => Some(Palette::from_colors(Rgb::new(0x000000), Rgb::new(0xFFFFFF))),
+            "amber" => Some(Palette::from_colors(Rgb::new(0x1A0F00), Rgb::new(0xFFB000))),
+            "green" | "green-phosphor" => Some(Palette::from_colors(Rgb::new(0x0A140A), Rgb::new(0x33FF66))),
+            "lcd" => Some(Palette {
+                colors: [Rgb::new(0x9BBC0F), Rgb::new(0x0F380F), Rgb::new(0x306230), Rgb::new(0x8BAC0F)]
+            }),
+            "high-contrast" => Some(Palette {
+                colors: [Rgb::new(0x000000), Rgb::new(0xFFFFFF), Rgb::new(0xFFFF00), Rgb::new(0x00FFFF)]
+            }),
+            _ => None
+        };
+    }
+
+    // A palette name, or two or four comma-separated colours starting with the background.
+    pub fn parse(text: &str) -> Option<Palette> {
+        if let Some(palette) = Palette::from_name(text) {
+            return Some(palette);
+        }
+        let colors: Option<Vec<Rgb>> = text.split(',').map(|color| Rgb::parse(color.trim())).collect();
+        return match colors?.as_slice() {
+            [background, foreground] => Some(Palette::from_colors(*background, *foreground)),
+            [a, b, c, d] => Some(Palette { colors: [*a, *b, *c, *d] }),
+            _ => None
+        };
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        return Palette::from_name("classic").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_matches_the_original_colours() {
+        let colors = Palette::default().colors;
+        assert_eq!(colors, [Rgb::new(0x000000), Rgb::new(0xFFFFFF), Rgb::new(0xAAAAAA), Rgb::new(0x555555)]);
+    }
+
+    #[test]
+    fn parses_names_and_colour_lists() {
+        for name in PALETTE_NAMES.iter() {
+            assert!(Palette::parse(name).is_some());
+        }
+        assert_eq!(Palette::parse("#102030,FFFFFF").unwrap().colors[0], Rgb { r: 0x10, g: 0x20, b: 0x30 });
+        let four = Palette::parse("000000, 111111, 222222, 333333").unwrap();
+        assert_eq!(four.colors[3], Rgb::new(0x333333));
+        assert!(Palette::parse("000000,111111,222222").is_none());
+        assert!(Palette::parse("12345G,000000").is_none());
+        assert!(Palette::parse("purple").is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use rip_8::display::{HIRES_HEIGHT, HIRES_WIDTH};
+use rip_8::palette::{Palette, Rgb};
+
+const PIXELS: usize = HIRES_WIDTH * HIRES_HEIGHT;
+
+// Ways of hiding the flicker of sprites that are erased and redrawn with XOR.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AntiFlicker {
+    Off,
+    // Pixels that turn off fade out over a number of frames.
+    Fade,
+    // Each pixel shows the OR of its last two frames.
+    Blend
+}
+
+pub const ANTI_FLICKER_NAMES: [&str; 3] = ["off", "fade", "blend"];
+
+impl AntiFlicker {
+    pub fn from_name(name: &str) -> Option<AntiFlicker> {
+        return match name.to_ascii_lowercase().as_str() {
+            "off" => Some(AntiFlicker::Off),
+            "fade" => Some(AntiFlicker::Fade),
+            "blend" => Some(AntiFlicker::Blend),
+            _ => None
+        };
+    }
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            AntiFlicker::Off => "off",
+            AntiFlicker::Fade => "fade",
+            AntiFlicker::Blend => "blend"
+        };
+    }
+
+    pub fn next(self) -> AntiFlicker {
+        return match self {
+            AntiFlicker::Off => AntiFlicker::Fade,
+            AntiFlicker::Fade => AntiFlicker::Blend,
+            AntiFlicker::Blend => AntiFlicker::Off
+        };
+    }
+}
+
+// The frames the renderer has received, and how far each unlit pixel has faded.
+pub struct Persistence {
+    pub mode: AntiFlicker,
+    fade_frames: u8,
+    current: [u8; PIXELS],
+    previous: [u8; PIXELS],
+    // For each pixel that has turned off: frames left until it is dark, and the value it had.
+    fade_left: [u8; PIXELS],
+    faded_from: [u8; PIXELS]
+}
+
+impl Persistence {
+    pub fn new(mode: AntiFlicker, fade_frames: u8) -> Persistence {
+        return Persistence {
+            mode,
+            fade_frames,
+            current: [0u8; PIXELS],
+            previous: [0u8; PIXELS],
+            fade_left: [0u8; PIXELS],
+            faded_from: [0u8; PIXELS]
+        };
+    }
+
+    // `previous` is the emulated frame before `pixels`, which need not be the last one pushed when the
+    // renderer misses frames. Fading advances by the `elapsed` emulated frames since the last push, so it
+    // takes as long whatever the display's refresh rate. A resolution change moves every pixel, so fading is
+    // dropped rather than smeared.
+    pub fn push_frame(&mut self, pixels: &[u8; PIXELS], previous: &[u8; PIXELS], elapsed: u8, resolution_changed: bool) {
+        self.previous = *previous;
+        self.current = *pixels;
+        if resolution_changed {
+            self.fade_left = [0u8; PIXELS];
+        }
+        for index in 0..PIXELS {
+            if self.current[index] != 0u8 {
+                self.fade_left[index] = self.fade_frames;
+                self.faded_from[index] = self.current[index];
+            } else {
+                self.fade_left[index] = self.fade_left[index].saturating_sub(elapsed);
+            }
+        }
+    }
+
+    pub fn color(&self, index: usize, palette: &Palette) -> Rgb {
+        let pixel = self.current[index];
+        return match self.mode {
+            AntiFlicker::Off => palette.colors[pixel as usize],
+            AntiFlicker::Blend => palette.colors[(pixel | self.previous[index]) as usize],
+            AntiFlicker::Fade if pixel == 0u8 && self.fade_left[index] > 0u8 => {
+                let background = palette.colors[0];
+                let lit = palette.colors[self.faded_from[index] as usize];
+                background.mix(lit, self.fade_left[index] as u32, self.fade_frames as u32 + 1)
+            }
+            AntiFlicker::Fade => palette.colors[pixel as usize]
+        };
+    }
+}
@@ -0,0 +1,114 @@
+// Behaviours that differ between CHIP-8 interpreters. Each preset matches the interpreter it is named
+// after; "modern" is the Cowgod's-reference behaviour most emulators (and Rip8 originally) implement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // 8xy6/8xyE: shift Vy into Vx instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    // Fx55/Fx65: how far I advances after the transfer.
+    pub load_store_increment: LoadStoreIncrement,
+    // Bnnn: jump to xnn + Vx instead of nnn + V0.
+    pub jump_uses_vx: bool,
+    // 8xy1/8xy2/8xy3: reset VF to 0.
+    pub logic_resets_vf: bool,
+    // Dxyn: clip sprites at the screen edges instead of wrapping them.
+    pub clip_sprites: bool,
+    // Dxyn: wait for the next vertical blank before drawing.
+    pub display_wait: bool,
+    // Fx0A: finish when the key is released, as the COSMAC VIP did, rather than when it is pressed.
+    pub key_wait_release: bool,
+    // Addressable memory. PC and I must stay below this; XO-CHIP extends it from 4K to 64K.
+    pub memory_size: usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadStoreIncrement {
+    None,
+    X,
+    XPlusOne
+}
+
+pub const PROFILE_NAMES: [&str; 5] = ["vip", "chip48", "schip", "xochip", "modern"];
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        return Quirks {
+            shift_uses_vy: true,
+            load_store_increment: LoadStoreIncrement::XPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+            key_wait_release: true,
+            memory_size: 0x1000
+        };
+    }
+
+    pub fn chip48() -> Quirks {
+        return Quirks {
+            shift_uses_vy: false,
+            load_store_increment: LoadStoreIncrement::X,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: false,
+            memory_size: 0x1000
+        };
+    }
+
+    pub fn superchip() -> Quirks {
+        return Quirks {
+            shift_uses_vy: false,
+            load_store_increment: LoadStoreIncrement::None,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: false,
+            memory_size: 0x1000
+        };
+    }
+
+    pub fn xochip() -> Quirks {
+        return Quirks {
+            shift_uses_vy: true,
+            load_store_increment: LoadStoreIncrement::XPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            key_wait_release: true,
+            memory_size: 0x10000
+        };
+    }
+
+    pub fn modern() -> Quirks {
+        return Quirks {
+            shift_uses_vy: false,
+            load_store_increment: LoadStoreIncrement::None,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            key_wait_release: false,
+            memory_size: 0x1000
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        return match name.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac-vip" | "chip8" | "chip-8" => Some(Quirks::cosmac_vip()),
+            "chip48" | "chip-48" => Some(Quirks::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Quirks::superchip()),
+            "xochip" | "xo-chip" | "octo" => Some(Quirks::xochip()),
+            "modern" => Some(Quirks::modern()),
+            _ => None
+        };
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        return Quirks::modern();
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+use std::cmp;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+use rip_8::display::{HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+use rip_8::palette::Palette;
+use crate::frame::Frame;
+use crate::persistence::{AntiFlicker, Persistence};
+
+// Draws the display by uploading it into a streaming texture, one per resolution, and letting SDL
+// scale that to the window by a whole number, centred with black bars around it.
+pub struct Renderer<'a> {
+    lores: Texture<'a>,
+    hires: Texture<'a>,
+    showing_hires: bool,
+    // The count of the last frame picked up.
+    frame_count: u64,
+    palette: Palette,
+    // Draw faint lines between CHIP-8 pixels.
+    pub grid: bool,
+    // Copies of the recent frames, so the frame lock is only held while copying.
+    persistence: Persistence
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>, palette: Palette, persistence: Persistence) -> Renderer<'a> {
+        let texture = |width: usize, height: usize| texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+            .unwrap();
+        return Renderer {
+            lores: texture(LORES_WIDTH, LORES_HEIGHT),
+            hires: texture(HIRES_WIDTH, HIRES_HEIGHT),
+            showing_hires: false,
+            frame_count: 0u64,
+            palette,
+            grid: false,
+            persistence
+        };
+    }
+
+    // Called once per displayed frame. Uploads the frame if the CPU thread has published a new one
+    // since the last call.
+    pub fn update(&mut self, frame: &Mutex<Frame>) {
+        let new_frame = {
+            let mut frame = frame.lock().unwrap();
+            let dirty = frame.dirty;
+            if dirty {
+                frame.dirty = false;
+                let elapsed = (frame.count - self.frame_count).min(u8::MAX as u64) as u8;
+                self.frame_count = frame.count;
+                self.persistence.push_frame(&frame.pixels, &frame.previous, elapsed, frame.hires != self.showing_hires);
+                self.showing_hires = frame.hires;
+            }
+            dirty
+        };
+        if new_frame {
+            self.upload();
+        }
+    }
+
+    pub fn anti_flicker(&self) -> AntiFlicker {
+        return self.persistence.mode;
+    }
+
+    pub fn set_anti_flicker(&mut self, mode: AntiFlicker) {
+        self.persistence.mode = mode;
+        self.upload();
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.upload();
+    }
+
+    fn upload(&mut self) {
+        let width = if self.showing_hires { HIRES_WIDTH } else { LORES_WIDTH };
+        let texture = if self.showing_hires { &mut self.hires } else { &mut self.lores };
+        let persistence = &self.persistence;
+        let palette = &self.palette;
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (y, row) in buffer.chunks_mut(pitch).enumerate() {
+                for x in 0..width {
+                    let color = persistence.color(y * width + x, palette);
+                    row[x * 3..x * 3 + 3].copy_from_slice(&[color.r, color.g, color.b]);
+                }
+            }
+        }).unwrap();
+    }
+
+    // With vsync on, this waits for the display refresh.
+    pub fn present(&mut self, canvas: &mut Canvas<Window>) {
+        let (texture, width, height) = if self.showing_hires {
+            (&self.hires, HIRES_WIDTH as u32, HIRES_HEIGHT as u32)
+        } else {
+            (&self.lores, LORES_WIDTH as u32, LORES_HEIGHT as u32)
+        };
+        let (output_width, output_height) = canvas.output_size().unwrap();
+        let scale = cmp::max(1u32, cmp::min(output_width / width, output_height / height));
+        let target = Rect::new(
+            (output_width as i32 - (width * scale) as i32) / 2,
+            (output_height as i32 - (height * scale) as i32) / 2,
+            width * scale,
+            height * scale
+        );
+        canvas.set_draw_color(Color::BLACK);
+        canvas.clear();
+        canvas.copy(texture, None, target).unwrap();
+        // Lines need a few window pixels per CHIP-8 pixel to leave anything visible between them.
+        if self.grid && scale >= 4u32 {
+            canvas.set_blend_mode(BlendMode::Blend);
+            canvas.set_draw_color(Color::RGBA(0x80, 0x80, 0x80, 0x30));
+            for column in 1..width {
+                let x = target.x() + (column * scale) as i32;
+                canvas.draw_line((x, target.y()), (x, target.bottom() - 1)).unwrap();
+            }
+            for row in 1..height {
+                let y = target.y() + (row * scale) as i32;
+                canvas.draw_line((target.x(), y), (target.right() - 1, y)).unwrap();
+            }
+            canvas.set_blend_mode(BlendMode::None);
+        }
+        canvas.present();
+    }
+}
@@ -0,0 +1,146 @@
+use crate::save_state::SaveState;
+use std::collections::VecDeque;
+
+// A run of bytes that differ between two snapshots, holding the older snapshot's values.
+struct Run {
+    start: usize,
+    bytes: Vec<u8>
+}
+
+// How to get from one snapshot back to the one before it. Memory and pixels are stored as runs of
+// changed bytes; the small fields are kept whole in `state`, whose memory and pixels are left empty.
+struct Delta {
+    state: SaveState,
+    memory: Vec<Run>,
+    pixels: Vec<Run>
+}
+
+// Ring buffer of snapshots for rewinding. Only the newest snapshot is stored in full; each older one
+// is a delta against its successor, so a frame that touches little memory costs little.
+pub struct Rewind {
+    capacity: usize,
+    latest: Option<SaveState>,
+    history: VecDeque<Delta>
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Rewind {
+        return Rewind { capacity, latest: None, history: VecDeque::new() };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.history.len() + if self.latest.is_some() { 1 } else { 0 };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.latest.is_none();
+    }
+
+    pub fn push(&mut self, state: SaveState) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(older) = self.latest.take() {
+            let memory = diff(&state.memory, &older.memory);
+            let pixels = diff(&state.pixels, &older.pixels);
+            self.history.push_back(Delta {
+                state: SaveState { memory: Vec::new(), pixels: Vec::new(), ..older },
+                memory,
+                pixels
+            });
+            if self.history.len() >= self.capacity {
+                self.history.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    // Removes and returns the newest snapshot.
+    pub fn pop(&mut self) -> Option<SaveState> {
+        let newest = self.latest.take()?;
+        if let Some(delta) = self.history.pop_back() {
+            let mut memory = newest.memory.clone();
+            apply(&mut memory, &delta.memory);
+            let mut pixels = newest.pixels.clone();
+            apply(&mut pixels, &delta.pixels);
+            self.latest = Some(SaveState { memory, pixels, ..delta.state });
+        }
+        return Some(newest);
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.history.clear();
+    }
+}
+
+// Runs where `older` differs from `newer`, with the bytes from `older`.
+fn diff(newer: &[u8], older: &[u8]) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for (index, (&new, &old)) in newer.iter().zip(older.iter()).enumerate() {
+        if new == old {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run) if run.start + run.bytes.len() == index => run.bytes.push(old),
+            _ => runs.push(Run { start: index, bytes: vec![old] })
+        }
+    }
+    return runs;
+}
+
+fn apply(target: &mut [u8], runs: &[Run]) {
+    for run in runs {
+        target[run.start..run.start + run.bytes.len()].copy_from_slice(&run.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+
+    fn snapshot(frame: u8) -> SaveState {
+        let mut machine = Machine::init();
+        machine.seed_rng(0);
+        machine.memory[0x300] = frame;
+        machine.memory[0x301] = frame;
+        machine.v[0] = frame;
+        machine.display.lock().unwrap().set(frame as usize, 0, 1);
+        return SaveState::capture(&machine);
+    }
+
+    #[test]
+    fn pops_snapshots_newest_first() {
+        let mut rewind = Rewind::new(10);
+        for frame in 0..5 {
+            rewind.push(snapshot(frame));
+        }
+        assert_eq!(rewind.len(), 5);
+        for frame in (0..5).rev() {
+            assert_eq!(rewind.pop().unwrap(), snapshot(frame));
+        }
+        assert!(rewind.pop().is_none());
+    }
+
+    #[test]
+    fn drops_the_oldest_snapshots_past_capacity() {
+        let mut rewind = Rewind::new(3);
+        for frame in 0..10 {
+            rewind.push(snapshot(frame));
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.pop().unwrap().v[0], 9);
+        assert_eq!(rewind.pop().unwrap().v[0], 8);
+        assert_eq!(rewind.pop().unwrap().v[0], 7);
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn deltas_only_hold_changed_bytes() {
+        let runs = diff(&[1, 2, 3, 4, 5], &[1, 9, 9, 4, 8]);
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].start, runs[0].bytes.clone()), (1, vec![9, 9]));
+        assert_eq!((runs[1].start, runs[1].bytes.clone()), (4, vec![8]));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::machine::Machine;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const PROGRAM_START: usize = 0x200;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    TooLarge { size: usize, max_size: usize }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "{}", error),
+            RomError::TooLarge { size, max_size } => write!(
+                f,
+                "ROM is {} bytes but at most {} bytes fit in memory after 0x{:03X}",
+                size, max_size, PROGRAM_START
+            )
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> RomError {
+        return RomError::Io(error);
+    }
+}
+
+pub fn load_rom(machine: &mut Machine, path: &Path) -> Result<(), RomError> {
+    return load_rom_data(machine, &fs::read(path)?);
+}
+
+pub fn load_rom_data(machine: &mut Machine, rom_data: &[u8]) -> Result<(), RomError> {
+    let max_size = machine.quirks.memory_size - PROGRAM_START;
+    if rom_data.len() > max_size {
+        return Err(RomError::TooLarge { size: rom_data.len(), max_size });
+    }
+
+    machine.memory[PROGRAM_START..PROGRAM_START + rom_data.len()].copy_from_slice(rom_data);
+    machine.pc = PROGRAM_START as u16;
+    return Ok(());
+}
@@ -0,0 +1,262 @@
+use crate::config::config_dir;
+use crate::palette::{Palette, Rgb};
+use crate::quirks::{LoadStoreIncrement, Quirks};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Settings for one ROM, taken from its database entry. Anything the entry leaves out is None, so
+// command line options and built-in defaults apply instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomInfo {
+    pub title: Option<String>,
+    // The database's id for the platform the ROM is run as, e.g. "superchip".
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    pub instructions_per_frame: Option<u32>,
+    pub palette: Option<Palette>,
+    // The CHIP-8 key the ROM uses for each of "up", "down", "left", "right", "a" and "b".
+    pub keys: Vec<(String, u8)>
+}
+
+#[derive(Debug)]
+pub enum RomDatabaseError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Format(String)
+}
+
+impl fmt::Display for RomDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomDatabaseError::Io(error) => write!(f, "{}", error),
+            RomDatabaseError::Json(error) => write!(f, "{}", error),
+            RomDatabaseError::Format(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for RomDatabaseError {}
+
+impl From<io::Error> for RomDatabaseError {
+    fn from(error: io::Error) -> RomDatabaseError {
+        return RomDatabaseError::Io(error);
+    }
+}
+
+impl From<serde_json::Error> for RomDatabaseError {
+    fn from(error: serde_json::Error) -> RomDatabaseError {
+        return RomDatabaseError::Json(error);
+    }
+}
+
+// ROM settings keyed by the SHA-1 of the ROM file, read from programs.json files in the format of the
+// CHIP-8 community database (https://github.com/chip-8/chip-8-database):
+//   [{
+//     "title": "Pong",
+//     "roms": {
+//       "<sha1 of the ROM>": {
+//         "platforms": ["superchip", "xochip"],
+//         "quirkyPlatforms": { "superchip": { "shift": false } },
+//         "tickrate": 30,
+//         "keys": { "up": 1, "down": 4 },
+//         "colors": { "pixels": ["#000000", "#ffffff"] }
+//       }
+//     }
+//   }]
+// Fields Rip8 has no use for, or cannot read, are ignored.
+#[derive(Debug, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>
+}
+
+impl RomDatabase {
+    pub fn parse(text: &str) -> Result<RomDatabase, RomDatabaseError> {
+        let programs = match serde_json::from_str::<Value>(text)? {
+            Value::Array(programs) => programs,
+            _ => return Err(RomDatabaseError::Format(String::from("Expected a list of programs")))
+        };
+        let mut roms = HashMap::new();
+        for program in programs.iter() {
+            let title = program.get("title").and_then(Value::as_str).map(String::from);
+            let entries = match program.get("roms").and_then(Value::as_object) {
+                Some(entries) => entries,
+                None => continue
+            };
+            for (hash, entry) in entries.iter() {
+                let mut info = rom_info(entry);
+                info.title = title.clone();
+                roms.insert(hash.to_ascii_lowercase(), info);
+            }
+        }
+        return Ok(RomDatabase { roms });
+    }
+
+    pub fn load(path: &Path) -> Result<RomDatabase, RomDatabaseError> {
+        return RomDatabase::parse(&fs::read_to_string(path)?);
+    }
+
+    // Entries in `other` replace any for the same ROM.
+    pub fn merge(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn lookup(&self, rom_data: &[u8]) -> Option<&RomInfo> {
+        return self.roms.get(&rom_hash(rom_data));
+    }
+}
+
+pub fn rom_hash(rom_data: &[u8]) -> String {
+    return sha1_smol::Sha1::from(rom_data).digest().to_string();
+}
+
+// The community database as programs.json in the configuration directory, then the user's own entries
+// from user-programs.json there, so they can add ROMs or change settings without editing the download.
+// Missing files are skipped.
+pub fn load_rom_database() -> Result<RomDatabase, String> {
+    let mut database = RomDatabase::default();
+    let paths: Vec<PathBuf> = match config_dir() {
+        Some(dir) => vec![dir.join("programs.json"), dir.join("user-programs.json")],
+        None => Vec::new()
+    };
+    for path in paths.iter().filter(|path| path.exists()) {
+        let entries = RomDatabase::load(path)
+            .map_err(|error| format!("Could not load ROM database {}: {}", path.display(), error))?;
+        database.merge(entries);
+    }
+    return Ok(database);
+}
+
+fn rom_info(entry: &Value) -> RomInfo {
+    let mut info = RomInfo::default();
+    // Platforms are listed best first; use the first one Rip8 emulates.
+    let platforms = entry.get("platforms").and_then(Value::as_array);
+    let platform = platforms.into_iter().flatten().filter_map(Value::as_str)
+        .find_map(|id| platform_quirks(id).map(|quirks| (id, quirks)));
+    if let Some((id, mut quirks)) = platform {
+        let overrides = entry.get("quirkyPlatforms").and_then(|quirky| quirky.get(id)).and_then(Value::as_object);
+        if let Some(overrides) = overrides {
+            apply_quirk_overrides(&mut quirks, overrides);
+        }
+        info.platform = Some(String::from(id));
+        info.quirks = Some(quirks);
+    }
+    info.instructions_per_frame = entry.get("tickrate").and_then(Value::as_u64)
+        .filter(|&tickrate| tickrate > 0 && tickrate <= u32::MAX as u64)
+        .map(|tickrate| tickrate as u32);
+    let colors: Option<Vec<Rgb>> = entry.get("colors").and_then(|colors| colors.get("pixels"))
+        .and_then(Value::as_array)
+        .and_then(|pixels| pixels.iter().map(|color| color.as_str().and_then(Rgb::parse)).collect());
+    info.palette = match colors.as_deref() {
+        Some([background, foreground]) => Some(Palette::from_colors(*background, *foreground)),
+        // XO-CHIP palettes can be longer; only the first four are needed for two bitplanes.
+        Some([a, b, c, d, ..]) => Some(Palette { colors: [*a, *b, *c, *d] }),
+        _ => None
+    };
+    if let Some(keys) = entry.get("keys").and_then(Value::as_object) {
+        info.keys = keys.iter()
+            .filter_map(|(role, key)| key.as_u64().filter(|&key| key < 16).map(|key| (role.clone(), key as u8)))
+            .collect();
+    }
+    return info;
+}
+
+// The database's platform ids. Ones that need hardware Rip8 does not emulate, like the CHIP-8X colour
+// board or MegaChip, are None.
+fn platform_quirks(id: &str) -> Option<Quirks> {
+    return match id {
+        "originalChip8" | "hybridVIP" => Some(Quirks::cosmac_vip()),
+        "modernChip8" => Some(Quirks::modern()),
+        "chip48" => Some(Quirks::chip48()),
+        "superchip1" => Some(Quirks { load_store_increment: LoadStoreIncrement::X, ..Quirks::superchip() }),
+        "superchip" => Some(Quirks::superchip()),
+        "xochip" => Some(Quirks::xochip()),
+        _ => None
+    };
+}
+
+// quirkyPlatforms entries describe the behaviour the ROM needs, as the database's quirk names.
+fn apply_quirk_overrides(quirks: &mut Quirks, overrides: &Map<String, Value>) {
+    let flag = |name: &str| overrides.get(name).and_then(Value::as_bool);
+    if let Some(shift) = flag("shift") {
+        quirks.shift_uses_vy = !shift;
+    }
+    match (flag("memoryLeaveIUnchanged"), flag("memoryIncrementByX")) {
+        (Some(true), _) => quirks.load_store_increment = LoadStoreIncrement::None,
+        (_, Some(true)) => quirks.load_store_increment = LoadStoreIncrement::X,
+        (Some(false), _) | (_, Some(false)) => quirks.load_store_increment = LoadStoreIncrement::XPlusOne,
+        (None, None) => {}
+    }
+    if let Some(wrap) = flag("wrap") {
+        quirks.clip_sprites = !wrap;
+    }
+    if let Some(jump) = flag("jump") {
+        quirks.jump_uses_vx = jump;
+    }
+    if let Some(vblank) = flag("vblank") {
+        quirks.display_wait = vblank;
+    }
+    if let Some(logic) = flag("logic") {
+        quirks.logic_resets_vf = logic;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 of an empty file.
+    const EMPTY_HASH: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+
+    #[test]
+    fn hashes_rom_files_with_sha1() {
+        assert_eq!(rom_hash(&[]), EMPTY_HASH);
+        assert_eq!(rom_hash(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn reads_community_database_entries() {
+        let database = RomDatabase::parse(&format!(r##"[{{
+            "title": "Test Game",
+            "authors": ["Someone"],
+            "roms": {{
+                "{}": {{
+                    "file": "test.ch8",
+                    "platforms": ["megachip8", "superchip", "xochip"],
+                    "quirkyPlatforms": {{ "superchip": {{ "wrap": true, "memoryIncrementByX": true }} }},
+                    "tickrate": 30,
+                    "keys": {{ "up": 2, "down": 8, "a": 16 }},
+                    "colors": {{ "pixels": ["#102030", "#ffffff"] }}
+                }}
+            }}
+        }}]"##, EMPTY_HASH.to_ascii_uppercase())).unwrap();
+        let info = database.lookup(&[]).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Test Game"));
+        assert_eq!(info.platform.as_deref(), Some("superchip"));
+        let quirks = info.quirks.unwrap();
+        assert!(!quirks.clip_sprites);
+        assert_eq!(quirks.load_store_increment, LoadStoreIncrement::X);
+        assert!(quirks.jump_uses_vx);
+        assert_eq!(info.instructions_per_frame, Some(30u32));
+        assert_eq!(info.palette.unwrap().colors[0], Rgb::new(0x102030));
+        assert_eq!(info.keys, vec![(String::from("down"), 8u8), (String::from("up"), 2u8)]);
+        assert!(database.lookup(b"abc").is_none());
+    }
+
+    #[test]
+    fn user_entries_replace_downloaded_ones() {
+        let entry = |title: &str, platform: &str| format!(
+            r#"[{{ "title": "{}", "roms": {{ "{}": {{ "platforms": ["{}"] }} }} }}]"#, title, EMPTY_HASH, platform
+        );
+        let mut database = RomDatabase::parse(&entry("Downloaded", "originalChip8")).unwrap();
+        database.merge(RomDatabase::parse(&entry("Mine", "chip8x")).unwrap());
+        let info = database.lookup(&[]).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Mine"));
+        assert_eq!(info.quirks, None);
+        assert!(RomDatabase::parse("{}").is_err());
+        assert!(RomDatabase::parse("[").is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use crate::display::{HIRES_HEIGHT, HIRES_WIDTH};
+use crate::machine::{KeyWait, Machine};
+use crate::quirks::{LoadStoreIncrement, Quirks};
+use crate::sound::Sound;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+const MAGIC: &[u8; 8] = b"RIP8SAVE";
+pub const STATE_VERSION: u16 = 4;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    NotAState,
+    UnsupportedVersion(u16),
+    Truncated,
+    // A field holds a value the machine could never have had.
+    OutOfRange(&'static str)
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io(error) => write!(f, "{}", error),
+            StateError::NotAState => write!(f, "Not a Rip8 save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "Save state version {} is not supported (expected {})",
+                version, STATE_VERSION
+            ),
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::OutOfRange(field) => write!(f, "Save state has an invalid {}", field)
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<io::Error> for StateError {
+    fn from(error: io::Error) -> StateError {
+        return StateError::Io(error);
+    }
+}
+
+// Everything that changes while a ROM runs, plus the quirks it runs under, so a state shared to reproduce
+// a bug replays the same way whatever options the other side uses.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SaveState {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub pc: u16,
+    pub sp: u16,
+    pub stack: [u16; 16],
+    pub keys: [bool; 16],
+    pub rpl: [u8; 16],
+    pub exited: bool,
+    pub key_wait: KeyWait,
+    pub vblank: bool,
+    pub planes: u8,
+    pub hires: bool,
+    pub pixels: Vec<u8>,
+    pub sound_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    pub rng: ChaCha8Rng,
+    pub quirks: Quirks
+}
+
+impl SaveState {
+    pub fn capture(machine: &Machine) -> SaveState {
+        let display = machine.display.lock().unwrap();
+        let sound = machine.sound.lock().unwrap();
+        return SaveState {
+            memory: machine.memory.to_vec(),
+            v: machine.v,
+            i: machine.i,
+            dt: *machine.dt.lock().unwrap(),
+            st: *machine.st.lock().unwrap(),
+            pc: machine.pc,
+            sp: machine.sp,
+            stack: machine.stack,
+            keys: machine.keys,
+            rpl: machine.rpl,
+            exited: machine.exited,
+            key_wait: machine.key_wait,
+            vblank: *machine.vblank.lock().unwrap(),
+            planes: machine.planes,
+            hires: display.hires,
+            pixels: display.pixels.to_vec(),
+            sound_pattern: sound.pattern,
+            pitch: sound.pitch,
+            rng: machine.rng.clone(),
+            quirks: machine.quirks
+        };
+    }
+
+    pub fn restore(&self, machine: &mut Machine) {
+        machine.memory.copy_from_slice(&self.memory);
+        machine.v = self.v;
+        machine.i = self.i;
+        *machine.dt.lock().unwrap() = self.dt;
+        *machine.st.lock().unwrap() = self.st;
+        machine.pc = self.pc;
+        machine.sp = self.sp;
+        machine.stack = self.stack;
+        machine.keys = self.keys;
+        machine.rpl = self.rpl;
+        machine.exited = self.exited;
+        machine.key_wait = self.key_wait;
+        *machine.vblank.lock().unwrap() = self.vblank;
+        machine.planes = self.planes;
+        machine.rng = self.rng.clone();
+        machine.quirks = self.quirks;
+        let mut display = machine.display.lock().unwrap();
+        display.hires = self.hires;
+        display.pixels.copy_from_slice(&self.pixels);
+        *machine.sound.lock().unwrap() = Sound { pattern: self.sound_pattern, pitch: self.pitch };
+    }
+
+    // Little-endian, fields in declaration order, after the magic and version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAGIC.len() + self.memory.len() + self.pixels.len() + 128);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.v);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.push(self.dt);
+        out.push(self.st);
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.sp.to_le_bytes());
+        for entry in self.stack.iter() {
+            out.extend_from_slice(&entry.to_le_bytes());
+        }
+        out.extend(self.keys.iter().map(|&pressed| pressed as u8));
+        out.extend_from_slice(&self.rpl);
+        out.push(self.exited as u8);
+        match self.key_wait {
+            KeyWait::Idle => out.push(0u8),
+            KeyWait::Waiting => out.push(1u8),
+            KeyWait::Done(key) => {
+                out.push(2u8);
+                out.push(key);
+            }
+        }
+        out.push(self.vblank as u8);
+        out.push(self.planes);
+        out.push(self.hires as u8);
+        out.extend_from_slice(&self.pixels);
+        match self.sound_pattern {
+            Some(pattern) => {
+                out.push(1u8);
+                out.extend_from_slice(&pattern);
+            }
+            None => out.push(0u8)
+        }
+        out.push(self.pitch);
+        // The generator as its seed, stream and position in that stream.
+        out.extend_from_slice(&self.rng.get_seed());
+        out.extend_from_slice(&self.rng.get_stream().to_le_bytes());
+        out.extend_from_slice(&self.rng.get_word_pos().to_le_bytes());
+        let quirks = &self.quirks;
+        for flag in [
+            quirks.shift_uses_vy, quirks.jump_uses_vx, quirks.logic_resets_vf, quirks.clip_sprites,
+            quirks.display_wait, quirks.key_wait_release
+        ] {
+            out.push(flag as u8);
+        }
+        out.push(match quirks.load_store_increment {
+            LoadStoreIncrement::None => 0u8,
+            LoadStoreIncrement::X => 1u8,
+            LoadStoreIncrement::XPlusOne => 2u8
+        });
+        out.extend_from_slice(&(quirks.memory_size as u32).to_le_bytes());
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, StateError> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let memory = reader.take(0x10000)?.to_vec();
+        let v = reader.array()?;
+        let i = reader.u16()?;
+        let dt = reader.u8()?;
+        let st = reader.u8()?;
+        let pc = reader.u16()?;
+        let sp = reader.u16()?;
+        if sp > 16u16 {
+            return Err(StateError::OutOfRange("stack pointer"));
+        }
+        let mut stack = [0u16; 16];
+        for entry in stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+        let mut keys = [false; 16];
+        for key in keys.iter_mut() {
+            *key = reader.u8()? != 0u8;
+        }
+        let rpl = reader.array()?;
+        let exited = reader.u8()? != 0u8;
+        let key_wait = match reader.u8()? {
+            0u8 => KeyWait::Idle,
+            1u8 => KeyWait::Waiting,
+            2u8 => match reader.u8()? {
+                key if key < 16u8 => KeyWait::Done(key),
+                _ => return Err(StateError::OutOfRange("key"))
+            },
+            _ => return Err(StateError::OutOfRange("key wait"))
+        };
+        let vblank = reader.u8()? != 0u8;
+        let planes = reader.u8()?;
+        if planes > 0b11u8 {
+            return Err(StateError::OutOfRange("plane selection"));
+        }
+        let hires = reader.u8()? != 0u8;
+        let pixels = reader.take(HIRES_WIDTH * HIRES_HEIGHT)?.to_vec();
+        // Each pixel holds one bit per plane.
+        if pixels.iter().any(|&pixel| pixel > 0b11u8) {
+            return Err(StateError::OutOfRange("pixel"));
+        }
+        let sound_pattern = if reader.u8()? != 0u8 {
+            Some(reader.array()?)
+        } else {
+            None
+        };
+        let pitch = reader.u8()?;
+        let mut rng = ChaCha8Rng::from_seed(reader.bytes()?);
+        rng.set_stream(u64::from_le_bytes(reader.bytes()?));
+        rng.set_word_pos(u128::from_le_bytes(reader.bytes()?));
+        let mut flags = [false; 6];
+        for flag in flags.iter_mut() {
+            *flag = reader.u8()? != 0u8;
+        }
+        let [shift_uses_vy, jump_uses_vx, logic_resets_vf, clip_sprites, display_wait, key_wait_release] = flags;
+        let load_store_increment = match reader.u8()? {
+            0u8 => LoadStoreIncrement::None,
+            1u8 => LoadStoreIncrement::X,
+            2u8 => LoadStoreIncrement::XPlusOne,
+            _ => return Err(StateError::OutOfRange("load/store quirk"))
+        };
+        let memory_size = u32::from_le_bytes(reader.bytes()?) as usize;
+        if !(0x1000..=0x10000).contains(&memory_size) {
+            return Err(StateError::OutOfRange("memory size"));
+        }
+        let quirks = Quirks {
+            shift_uses_vy, load_store_increment, jump_uses_vx, logic_resets_vf, clip_sprites, display_wait,
+            key_wait_release, memory_size
+        };
+        return Ok(SaveState {
+            memory, v, i, dt, st, pc, sp, stack, keys, rpl, exited, key_wait, vblank, planes, hires, pixels,
+            sound_pattern, pitch, rng, quirks
+        });
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self.bytes.get(self.position..self.position + count).ok_or(StateError::Truncated)?;
+        self.position += count;
+        return Ok(bytes);
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.take(2)?;
+        return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    fn array(&mut self) -> Result<[u8; 16], StateError> {
+        return self.bytes();
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.take(N)?);
+        return Ok(bytes);
+    }
+}
+
+// Slot files sit next to the ROM: slot 1 of "games/pong.ch8" is "games/pong.state1".
+pub fn state_path(rom_path: &Path, slot: u8) -> PathBuf {
+    return rom_path.with_extension(format!("state{}", slot));
+}
+
+pub fn save_state(machine: &Machine, path: &Path) -> Result<(), StateError> {
+    fs::write(path, SaveState::capture(machine).to_bytes())?;
+    return Ok(());
+}
+
+// The file is fully read and checked before the machine is touched, so a bad file leaves it running as before.
+pub fn load_state(machine: &mut Machine, path: &Path) -> Result<(), StateError> {
+    let state = SaveState::from_bytes(&fs::read(path)?)?;
+    state.restore(machine);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn round_trips_every_field() {
+        let mut machine = Machine::init();
+        machine.memory[0x300] = 0xAB;
+        machine.v[3] = 7;
+        machine.i = 0x1234;
+        *machine.dt.lock().unwrap() = 9;
+        machine.pc = 0x246;
+        machine.sp = 2;
+        machine.stack[1] = 0x222;
+        machine.keys[5] = true;
+        machine.planes = 0b11;
+        machine.display.lock().unwrap().set_hires(true);
+        machine.display.lock().unwrap().set(100, 50, 0b10);
+        machine.sound.lock().unwrap().pattern = Some([0x55; 16]);
+        machine.seed_rng(42);
+        machine.rng.gen::<u32>();
+        machine.quirks = Quirks::xochip();
+        let state = SaveState::capture(&machine);
+
+        let mut restored = Machine::init();
+        SaveState::from_bytes(&state.to_bytes()).unwrap().restore(&mut restored);
+        assert_eq!(SaveState::capture(&restored), state);
+        assert_eq!(restored.display.lock().unwrap().get(100, 50), 0b10);
+        assert_eq!(restored.quirks, Quirks::xochip());
+        assert_eq!(restored.rng.gen::<u64>(), machine.rng.gen::<u64>());
+    }
+
+    #[test]
+    fn round_trips_a_key_wait() {
+        let mut machine = Machine::init();
+        machine.memory[0x200] = 0xF2;
+        machine.memory[0x201] = 0x0A;
+        machine.quirks.display_wait = true;
+        *machine.vblank.lock().unwrap() = true;
+        machine.key_wait = KeyWait::Waiting;
+        let state = SaveState::capture(&machine);
+
+        let mut restored = Machine::init();
+        SaveState::from_bytes(&state.to_bytes()).unwrap().restore(&mut restored);
+        assert_eq!(restored.key_wait, KeyWait::Waiting);
+        assert!(*restored.vblank.lock().unwrap());
+        machine.key_wait = KeyWait::Done(0xB);
+        let state = SaveState::from_bytes(&SaveState::capture(&machine).to_bytes()).unwrap();
+        assert_eq!(state.key_wait, KeyWait::Done(0xB));
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let bytes = SaveState::capture(&Machine::init()).to_bytes();
+        assert!(matches!(SaveState::from_bytes(b"not a state"), Err(StateError::NotAState)));
+        assert!(matches!(SaveState::from_bytes(&bytes[..100]), Err(StateError::Truncated)));
+        let mut future = bytes.clone();
+        future[8] = 99;
+        assert!(matches!(SaveState::from_bytes(&future), Err(StateError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let corrupt = |change: fn(&mut SaveState)| {
+            let mut state = SaveState::capture(&Machine::init());
+            change(&mut state);
+            return SaveState::from_bytes(&state.to_bytes()).err().unwrap().to_string();
+        };
+        assert_eq!(corrupt(|state| state.sp = 17), "Save state has an invalid stack pointer");
+        assert_eq!(corrupt(|state| state.planes = 4), "Save state has an invalid plane selection");
+        assert_eq!(corrupt(|state| state.pixels[10] = 4), "Save state has an invalid pixel");
+        assert_eq!(corrupt(|state| state.key_wait = KeyWait::Done(16)), "Save state has an invalid key");
+        assert_eq!(corrupt(|state| state.quirks.memory_size = 0x20000), "Save state has an invalid memory size");
+    }
+
+    #[test]
+    fn slots_sit_next_to_the_rom() {
+        assert_eq!(state_path(Path::new("roms/pong.ch8"), 3), PathBuf::from("roms/pong.state3"));
+    }
+}
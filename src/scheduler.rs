@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+pub const FRAMES_PER_SECOND: u64 = 60;
+
+// Frames later than this are dropped rather than run back to back, e.g. after sitting in the debugger.
+const MAX_LAG_FRAMES: u64 = 6;
+
+// Paces emulation at exactly 60 frames per second of wall-clock time. Frame deadlines are computed
+// from the frame count rather than by adding up sleeps, so oversleeping on one frame shortens the
+// next sleep instead of accumulating as drift.
+pub struct FrameScheduler {
+    origin: Instant,
+    frames: u64
+}
+
+impl FrameScheduler {
+    pub fn new(now: Instant) -> FrameScheduler {
+        return FrameScheduler { origin: now, frames: 0u64 };
+    }
+
+    fn deadline(&self) -> Instant {
+        return self.origin + Duration::from_nanos(self.frames * 1_000_000_000 / FRAMES_PER_SECOND);
+    }
+
+    // Ends the current frame and returns how long to sleep before starting the next one.
+    pub fn end_frame(&mut self, now: Instant) -> Duration {
+        self.frames += 1;
+        let deadline = self.deadline();
+        let max_lag = Duration::from_nanos(MAX_LAG_FRAMES * 1_000_000_000 / FRAMES_PER_SECOND);
+        if now > deadline + max_lag {
+            self.origin = now;
+            self.frames = 0u64;
+            return Duration::ZERO;
+        }
+        return deadline.saturating_duration_since(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sixty_frames_take_exactly_one_second() {
+        let start = Instant::now();
+        let mut scheduler = FrameScheduler::new(start);
+        let mut now = start;
+        for _ in 0..60 {
+            now += scheduler.end_frame(now);
+        }
+        assert_eq!(now - start, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn a_late_frame_shortens_the_next_sleep() {
+        let start = Instant::now();
+        let mut scheduler = FrameScheduler::new(start);
+        // The first frame ended 20 ms in, past its deadline, so the second sleeps only until its own.
+        let sleep = scheduler.end_frame(start + Duration::from_millis(20));
+        assert_eq!(sleep, Duration::ZERO);
+        let sleep = scheduler.end_frame(start + Duration::from_millis(21));
+        assert_eq!(sleep, Duration::from_nanos(33_333_333) - Duration::from_millis(21));
+    }
+
+    #[test]
+    fn a_long_stall_restarts_the_schedule() {
+        let start = Instant::now();
+        let mut scheduler = FrameScheduler::new(start);
+        let resumed = start + Duration::from_secs(5);
+        assert_eq!(scheduler.end_frame(resumed), Duration::ZERO);
+        assert_eq!(scheduler.end_frame(resumed), Duration::from_nanos(16_666_666));
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
+use rip_8::display::Display;
+use rip_8::hooks::Hooks;
+use rip_8::key_event::KeyEvent;
+use crate::frame::Frame;
+
+pub struct SdlHooks {
+    pub key_receiver: Receiver<KeyEvent>,
+    pub frame: Arc<Mutex<Frame>>
+}
+
+impl SdlHooks {
+    // Called at the end of each frame, so the renderer only ever gets complete frames. Every frame is
+    // published, changed or not, since blending depends on the frame before it.
+    pub fn present(&mut self, display: &Mutex<Display>) {
+        self.frame.lock().unwrap().publish(display);
+    }
+}
+
+impl Hooks for SdlHooks {
+    fn poll_key(&mut self) -> Option<KeyEvent> {
+        return self.key_receiver.try_recv().ok();
+    }
+
+    fn display_updated(&mut self) {}
+}
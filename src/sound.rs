@@ -0,0 +1,26 @@
+// XO-CHIP audio state, shared with the frontend's audio callback.
+pub struct Sound {
+    // The 128-bit pattern loaded by F002. None until a ROM uses it, in which case a plain beep plays.
+    pub pattern: Option<[u8; 16]>,
+    pub pitch: u8
+}
+
+impl Sound {
+    pub fn new() -> Sound {
+        return Sound {
+            pattern: None,
+            pitch: 64u8
+        };
+    }
+
+    // Pattern bits played per second; pitch 64 is 4000 Hz.
+    pub fn playback_rate(&self) -> f32 {
+        return 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+    }
+}
+
+impl Default for Sound {
+    fn default() -> Sound {
+        return Sound::new();
+    }
+}
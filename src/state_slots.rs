@@ -0,0 +1,38 @@
+use rip_8::machine::Machine;
+use rip_8::save_state::{load_state, save_state, state_path};
+use std::path::Path;
+
+pub const SLOT_COUNT: u8 = 10;
+
+// Sent from the event loop and carried out by the CPU thread between instructions.
+pub enum StateRequest {
+    Save(u8),
+    Load(u8)
+}
+
+// Returns true if the machine state was replaced.
+pub fn handle_state_request(machine: &mut Machine, rom_path: &Path, request: StateRequest) -> bool {
+    return match request {
+        StateRequest::Save(slot) => {
+            let path = state_path(rom_path, slot);
+            match save_state(machine, &path) {
+                Ok(()) => println!("Saved state to slot {} ({})", slot, path.display()),
+                Err(error) => eprintln!("rip_8: Could not save state to {}: {}", path.display(), error)
+            }
+            false
+        }
+        StateRequest::Load(slot) => {
+            let path = state_path(rom_path, slot);
+            match load_state(machine, &path) {
+                Ok(()) => {
+                    println!("Loaded state from slot {}", slot);
+                    true
+                }
+                Err(error) => {
+                    eprintln!("rip_8: Could not load state from {}: {}", path.display(), error);
+                    false
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use sdl2::video::Window;
+use rip_8::config::config_dir;
+
+// Size and position of the window when it is not fullscreen, kept between runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl WindowGeometry {
+    pub fn of(window: &Window) -> WindowGeometry {
+        let (x, y) = window.position();
+        let (width, height) = window.size();
+        return WindowGeometry { x, y, width, height };
+    }
+
+    fn path() -> Option<PathBuf> {
+        return config_dir().map(|dir| dir.join("window"));
+    }
+
+    // A missing or unreadable file just means the window opens at its default size.
+    pub fn load() -> Option<WindowGeometry> {
+        let text = fs::read_to_string(WindowGeometry::path()?).ok()?;
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        return match fields.as_slice() {
+            [x, y, width, height] => Some(WindowGeometry {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                width: width.parse().ok().filter(|&width| width > 0)?,
+                height: height.parse().ok().filter(|&height| height > 0)?
+            }),
+            _ => None
+        };
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = WindowGeometry::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No configuration directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        return fs::write(path, format!("{} {} {} {}\n", self.x, self.y, self.width, self.height));
+    }
+}
@@ -0,0 +1,52 @@
+#![allow(clippy::needless_return)]
+
+use rip_8::dump::display_ascii;
+use rip_8::hooks::Hooks;
+use rip_8::key_event::KeyEvent;
+use rip_8::machine::Machine;
+use rip_8::rom::load_rom;
+use std::fs;
+use std::path::Path;
+
+struct NoInput;
+
+impl Hooks for NoInput {
+    fn poll_key(&mut self) -> Option<KeyEvent> {
+        return None;
+    }
+
+    fn display_updated(&mut self) {}
+}
+
+// Runs the ROM until it parks itself on a jump to its own address.
+fn run_to_completion(rom: &str) -> Machine {
+    let mut machine = Machine::init();
+    machine.seed_rng(0);
+    load_rom(&mut machine, &Path::new("roms").join(rom)).unwrap();
+
+    for _ in 0..100_000 {
+        let pc = machine.pc;
+        machine.step(&mut NoInput).unwrap();
+        if machine.pc == pc {
+            return machine;
+        }
+    }
+    panic!("{} did not finish", rom);
+}
+
+fn assert_golden(machine: &Machine, golden: &str) {
+    let expected = fs::read_to_string(Path::new("tests/golden").join(golden)).unwrap();
+    assert_eq!(display_ascii(machine), expected, "display differs from {}", golden);
+}
+
+#[test]
+fn test_opcode() {
+    let machine = run_to_completion("test_opcode.ch8");
+    assert_golden(&machine, "test_opcode.txt");
+}
+
+#[test]
+fn chip8_test_rom() {
+    let machine = run_to_completion("chip8-test-rom.ch8");
+    assert_golden(&machine, "chip8-test-rom.txt");
+}